@@ -0,0 +1,349 @@
+use crate::{
+    create_private,
+    data_dir::{wallet_files, DataDir, WALLET_FILES},
+    error::CliError,
+    read_file, sync_parent_dir, write_atomically,
+};
+use celestium::wallet::BinaryWallet;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{Read, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Directory inside a data dir that backups of it are kept in.
+const BACKUPS_DIR: &str = ".backups";
+
+/// Written last into a backup, a backup without one is incomplete.
+const MANIFEST_FILE: &str = "MANIFEST";
+
+/// Timestamped, gzipped snapshots of the wallet files of a data dir.
+pub struct Backups<'a> {
+    data_dir: &'a DataDir,
+}
+
+/// Describes a complete backup, the checksums are of the uncompressed files.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BackupManifest {
+    pub id: String,
+    /// Seconds since the Unix epoch
+    pub created: u64,
+    pub files: BTreeMap<String, BackupFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BackupFile {
+    pub size: usize,
+    pub sha3_256: String,
+}
+
+/// Formats `secs` since the Unix epoch as a compact UTC timestamp, like
+/// `20211018T134502Z`.
+fn timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+    // Civil date from days since the epoch, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// The `n` of an id made unique as `<timestamp>-<n>`, 1 for the first
+/// backup of a second, which has no suffix.
+fn id_suffix(id: &str) -> u32 {
+    id.rsplit_once('-')
+        .and_then(|(_, n)| n.parse().ok())
+        .unwrap_or(1)
+}
+
+impl<'a> Backups<'a> {
+    pub fn new(data_dir: &'a DataDir) -> Backups<'a> {
+        Backups { data_dir }
+    }
+
+    pub fn path(&self) -> String {
+        self.data_dir.file_path(BACKUPS_DIR)
+    }
+
+    fn backup_dir(&self, id: &str) -> String {
+        format!("{}/{}", self.path(), id)
+    }
+
+    fn backup_path(&self, id: &str, name: &str) -> String {
+        format!("{}/{}", self.backup_dir(id), name)
+    }
+
+    /// Snapshots `bin_wallet`, as read from the data dir, into a new backup.
+    pub fn create(&self, bin_wallet: &BinaryWallet) -> Result<BackupManifest, CliError> {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let base_id = timestamp(created);
+        let mut id = base_id.clone();
+        let mut n = 1;
+        while Path::new(&self.backup_dir(&id)).exists() {
+            n += 1;
+            id = format!("{}-{}", base_id, n);
+        }
+
+        // Written under a temporary name and renamed once complete
+        let tmp_id = format!("{}.tmp", id);
+        let tmp_dir = self.backup_dir(&tmp_id);
+        if Path::new(&tmp_dir).exists() {
+            fs::remove_dir_all(&tmp_dir).map_err(|e| CliError::io(&tmp_dir, e))?;
+        }
+        fs::create_dir_all(&tmp_dir).map_err(|e| CliError::io(&tmp_dir, e))?;
+        let mut files = BTreeMap::new();
+        for (name, data) in wallet_files(bin_wallet).iter() {
+            let path = self.backup_path(&tmp_id, &format!("{}.gz", name));
+            let file = if *name == "sk" {
+                create_private(&path)
+            } else {
+                File::create(&path)
+            }
+            .map_err(|e| CliError::io(&path, e))?;
+            let mut encoder = GzEncoder::new(file, Compression::default());
+            encoder
+                .write_all(data)
+                .and_then(|_| encoder.finish())
+                .and_then(|file| file.sync_all())
+                .map_err(|e| CliError::io(&path, e))?;
+            files.insert(
+                name.to_string(),
+                BackupFile {
+                    size: data.len(),
+                    sha3_256: hex::encode(Sha3_256::digest(data)),
+                },
+            );
+        }
+        let manifest = BackupManifest { id, created, files };
+        let manifest_path = self.backup_path(&tmp_id, MANIFEST_FILE);
+        let manifest_bin = serde_json::to_vec_pretty(&manifest)
+            .map_err(|e| CliError::serialization(&manifest_path, None, e))?;
+        write_atomically(&manifest_path, &manifest_bin)
+            .map_err(|e| CliError::io(&manifest_path, e))?;
+        let backup_dir = self.backup_dir(&manifest.id);
+        fs::rename(&tmp_dir, &backup_dir)
+            .and_then(|_| sync_parent_dir(&backup_dir))
+            .map_err(|e| CliError::io(&backup_dir, e))?;
+        Ok(manifest)
+    }
+
+    /// Deletes all but the newest `keep` complete backups, returning the ones
+    /// deleted.
+    pub fn prune(&self, keep: usize) -> Result<Vec<BackupManifest>, CliError> {
+        let mut backups = self.list()?;
+        let prune = backups.len().saturating_sub(keep);
+        let pruned: Vec<BackupManifest> = backups.drain(..prune).collect();
+        for manifest in &pruned {
+            let backup_dir = self.backup_dir(&manifest.id);
+            // The manifest goes first, so a prune cut short leaves an
+            // incomplete backup rather than a corrupt one
+            let manifest_path = self.backup_path(&manifest.id, MANIFEST_FILE);
+            fs::remove_file(&manifest_path).map_err(|e| CliError::io(&manifest_path, e))?;
+            fs::remove_dir_all(&backup_dir).map_err(|e| CliError::io(&backup_dir, e))?;
+        }
+        Ok(pruned)
+    }
+
+    /// Complete backups, oldest first.
+    pub fn list(&self) -> Result<Vec<BackupManifest>, CliError> {
+        let path = self.path();
+        if !Path::new(&path).exists() {
+            return Ok(Vec::new());
+        }
+        let mut backups = Vec::new();
+        for entry in fs::read_dir(&path).map_err(|e| CliError::io(&path, e))? {
+            let entry = entry.map_err(|e| CliError::io(&path, e))?;
+            let id = entry.file_name().to_string_lossy().to_string();
+            if id.ends_with(".tmp") || !Path::new(&self.backup_path(&id, MANIFEST_FILE)).exists() {
+                continue;
+            }
+            backups.push(self.manifest(&id)?);
+        }
+        backups.sort_by_key(|manifest| (manifest.created, id_suffix(&manifest.id)));
+        Ok(backups)
+    }
+
+    pub fn manifest(&self, id: &str) -> Result<BackupManifest, CliError> {
+        if id.is_empty() || id.contains('/') || id.starts_with('.') {
+            return Err(CliError::Usage(format!("Invalid backup id \"{}\"", id)));
+        }
+        if !Path::new(&self.backup_dir(id)).exists() {
+            return Err(CliError::Usage(format!(
+                "No backup \"{}\" in \"{}\"",
+                id,
+                self.path()
+            )));
+        }
+        let path = self.backup_path(id, MANIFEST_FILE);
+        serde_json::from_slice(&read_file(&path)?)
+            .map_err(|e| CliError::serialization(&path, None, e))
+    }
+
+    /// Decompresses backup `id`, checking every file against the manifest.
+    pub fn load(&self, id: &str) -> Result<BinaryWallet, CliError> {
+        let manifest = self.manifest(id)?;
+        let load = |name: &str| -> Result<Vec<u8>, CliError> {
+            let path = self.backup_path(id, &format!("{}.gz", name));
+            let expected = manifest.files.get(name).ok_or_else(|| {
+                CliError::serialization(
+                    &self.backup_path(id, MANIFEST_FILE),
+                    None,
+                    format!("No entry for {}", name),
+                )
+            })?;
+            let mut data = Vec::with_capacity(expected.size);
+            GzDecoder::new(read_file(&path)?.as_slice())
+                .read_to_end(&mut data)
+                .map_err(|e| CliError::serialization(&path, None, e))?;
+            if hex::encode(Sha3_256::digest(&data)) != expected.sha3_256 {
+                return Err(CliError::serialization(
+                    &path,
+                    None,
+                    "Checksum does not match the backup manifest",
+                ));
+            }
+            Ok(data)
+        };
+        Ok(BinaryWallet {
+            blockchain_bin: load(WALLET_FILES[0])?,
+            pk_bin: load(WALLET_FILES[1])?,
+            sk_bin: load(WALLET_FILES[2])?,
+            on_chain_transactions_bin: load(WALLET_FILES[3])?,
+            unspent_outputs_bin: load(WALLET_FILES[4])?,
+            nft_lookups_bin: load(WALLET_FILES[5])?,
+            off_chain_transactions_bin: load(WALLET_FILES[6])?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn bin_wallet(blockchain: &[u8]) -> BinaryWallet {
+        BinaryWallet {
+            blockchain_bin: blockchain.to_vec(),
+            pk_bin: b"pk".to_vec(),
+            sk_bin: b"sk".to_vec(),
+            on_chain_transactions_bin: b"on_chain_transactions".to_vec(),
+            unspent_outputs_bin: b"unspent_outputs".to_vec(),
+            nft_lookups_bin: Vec::new(),
+            off_chain_transactions_bin: b"off_chain_transactions".to_vec(),
+        }
+    }
+
+    fn open(dir: &TempDir) -> DataDir {
+        DataDir::open(dir.path().to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp(0), "19700101T000000Z");
+        assert_eq!(timestamp(951_782_400), "20000229T000000Z");
+        assert_eq!(timestamp(1_634_564_702), "20211018T134502Z");
+    }
+
+    #[test]
+    fn suffixes_sort_numerically() {
+        assert_eq!(id_suffix("20211018T134502Z"), 1);
+        assert_eq!(id_suffix("20211018T134502Z-2"), 2);
+        assert_eq!(id_suffix("20211018T134502Z-10"), 10);
+    }
+
+    #[test]
+    fn create_then_load_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let data_dir = open(&dir);
+        let backups = Backups::new(&data_dir);
+        let bin_wallet = bin_wallet(b"blockchain");
+        let manifest = backups.create(&bin_wallet).unwrap();
+        assert_eq!(manifest.files.len(), WALLET_FILES.len());
+        let loaded = backups.load(&manifest.id).unwrap();
+        for ((name, data), (_, expected)) in wallet_files(&loaded)
+            .iter()
+            .zip(wallet_files(&bin_wallet).iter())
+        {
+            assert_eq!(data, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn load_refuses_corrupt_backup() {
+        let dir = TempDir::new().unwrap();
+        let data_dir = open(&dir);
+        let backups = Backups::new(&data_dir);
+        let id = backups.create(&bin_wallet(b"blockchain")).unwrap().id;
+        let path = backups.backup_path(&id, "blockchain.gz");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"corrupt").unwrap();
+        fs::write(&path, encoder.finish().unwrap()).unwrap();
+        match backups.load(&id) {
+            Err(CliError::Serialization {
+                path: error_path, ..
+            }) => assert_eq!(error_path, path),
+            Err(e) => panic!("Expected a serialization error, got {}", e),
+            Ok(_) => panic!("Expected an error"),
+        }
+    }
+
+    #[test]
+    fn prune_keeps_newest() {
+        let dir = TempDir::new().unwrap();
+        let data_dir = open(&dir);
+        let backups = Backups::new(&data_dir);
+        // Created within the same second or two, so most get a suffix
+        let ids: Vec<String> = (0..12)
+            .map(|i| {
+                backups
+                    .create(&bin_wallet(format!("blockchain {}", i).as_bytes()))
+                    .unwrap()
+                    .id
+            })
+            .collect();
+        let listed: Vec<String> = backups.list().unwrap().into_iter().map(|m| m.id).collect();
+        assert_eq!(listed, ids);
+
+        let pruned: Vec<String> = backups
+            .prune(3)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.id)
+            .collect();
+        assert_eq!(pruned, ids[..9].to_vec());
+        let listed: Vec<String> = backups.list().unwrap().into_iter().map(|m| m.id).collect();
+        assert_eq!(listed, ids[9..].to_vec());
+        for id in &ids[..9] {
+            assert!(!Path::new(&backups.backup_dir(id)).exists());
+        }
+        assert_eq!(
+            backups.load(&ids[11]).unwrap().blockchain_bin,
+            b"blockchain 11"
+        );
+        assert!(backups.prune(3).unwrap().is_empty());
+    }
+}
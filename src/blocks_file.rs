@@ -0,0 +1,567 @@
+use crate::{error::CliError, sync_parent_dir};
+use celestium::{
+    block::Block,
+    serialize::{DynamicSized, Serialize},
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+
+/// Marker following the last block of a blocks file.
+pub const END_MAGIC: [u8; 4] = [0x41, 0x41, 0x41, 0x41];
+
+/// How much more of a blocks file is read whenever a block does not fit in
+/// what has been buffered so far.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Largest block a blocks file is expected to hold. A block that still fails
+/// to parse with this much buffered is reported as corrupt rather than read
+/// further into memory.
+pub const MAX_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+
+/// A binary blocks file: serialized blocks back to back, optionally followed
+/// by the end magic and whatever trailer comes after it.
+///
+/// Blocks are streamed from and to disk, so only the block being worked on is
+/// held in memory no matter how long the chain is.
+pub struct BlocksFile {
+    path: String,
+}
+
+/// A block read from a blocks file, along with the byte range it occupies.
+pub struct BlockEntry {
+    pub block: Block,
+    pub offset: usize,
+    pub end: usize,
+}
+
+/// Reads a block from the start of the buffer, advancing the offset past it.
+type ParseBlock = fn(&[u8], &mut usize) -> Result<Box<Block>, String>;
+
+/// Iterator over the blocks of a blocks file, stopping at the end magic.
+///
+/// Yields an error and stops when a block cannot be parsed or the file ends
+/// in the middle of the end magic.
+pub struct BlocksReader<R> {
+    path: String,
+    reader: R,
+    buf: Vec<u8>,
+    pos: usize,
+    offset: usize,
+    eof: bool,
+    end_magic: Option<usize>,
+    done: bool,
+    parse: ParseBlock,
+    max_block_size: usize,
+}
+
+/// Writes a blocks file to a `.partial` sibling, which replaces the file once
+/// committed. Until then the original is left untouched.
+pub struct BlocksWriter {
+    path: String,
+    partial_path: String,
+    writer: BufWriter<File>,
+    len: usize,
+}
+
+impl BlocksFile {
+    pub fn new(path: &str) -> BlocksFile {
+        BlocksFile {
+            path: path.to_string(),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn blocks(&self) -> Result<BlocksReader<BufReader<File>>, CliError> {
+        self.blocks_from(0)
+    }
+
+    /// Streams the blocks starting at byte `offset`, which must be the start
+    /// of a block.
+    pub fn blocks_from(&self, offset: usize) -> Result<BlocksReader<BufReader<File>>, CliError> {
+        let mut file = File::open(&self.path).map_err(|e| CliError::io(&self.path, e))?;
+        let len = file
+            .metadata()
+            .map_err(|e| CliError::io(&self.path, e))?
+            .len();
+        if offset as u64 > len {
+            return Err(CliError::serialization(
+                &self.path,
+                Some(offset),
+                format!("Offset is past the end of the {}B file", len),
+            ));
+        }
+        file.seek(SeekFrom::Start(offset as u64))
+            .map_err(|e| CliError::io(&self.path, e))?;
+        Ok(BlocksReader::new(&self.path, BufReader::new(file), offset))
+    }
+
+    /// Offset of the end magic, `None` if the file ends right after its last
+    /// block. Scans every block, so fails like [`BlocksFile::blocks`] does.
+    pub fn end_magic(&self) -> Result<Option<usize>, CliError> {
+        let mut blocks = self.blocks()?;
+        for entry in blocks.by_ref() {
+            entry?;
+        }
+        Ok(blocks.end_magic())
+    }
+
+    /// Adds `block` after the last block, ahead of the end magic if present,
+    /// returning the range it occupies.
+    pub fn append(&self, block: &Block) -> Result<Range<usize>, CliError> {
+        let mut writer = BlocksWriter::create(&self.path)?;
+        let mut blocks = self.blocks()?;
+        for entry in blocks.by_ref() {
+            writer.write_block(&entry?.block)?;
+        }
+        let range = writer.write_block(block)?;
+        blocks.copy_rest(&mut writer)?;
+        writer.commit()?;
+        Ok(range)
+    }
+}
+
+impl<R: Read> BlocksReader<R> {
+    /// Reads blocks from `reader`, which is positioned at byte `offset` of the
+    /// file at `path`.
+    pub fn new(path: &str, reader: R, offset: usize) -> BlocksReader<R> {
+        BlocksReader {
+            path: path.to_string(),
+            reader,
+            buf: Vec::new(),
+            pos: 0,
+            offset,
+            eof: false,
+            end_magic: None,
+            done: false,
+            parse: Block::from_serialized,
+            max_block_size: MAX_BLOCK_SIZE,
+        }
+    }
+
+    /// Offset of the next block to be read.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Offset of the end magic, once the iterator has reached it.
+    pub fn end_magic(&self) -> Option<usize> {
+        self.end_magic
+    }
+
+    /// Copies everything from the current offset to the end of the file,
+    /// such as the end magic and trailer once the blocks are exhausted.
+    pub fn copy_rest(mut self, writer: &mut BlocksWriter) -> Result<(), CliError> {
+        writer.write_raw(&self.buf[self.pos..])?;
+        let mut chunk = vec![0u8; READ_CHUNK_SIZE];
+        loop {
+            let n = self
+                .reader
+                .read(&mut chunk)
+                .map_err(|e| CliError::io(&self.path, e))?;
+            if n == 0 {
+                return Ok(());
+            }
+            writer.write_raw(&chunk[..n])?;
+        }
+    }
+
+    /// Buffers until at least `len` unread bytes are available or the file
+    /// ends, dropping what has already been read.
+    fn fill(&mut self, len: usize) -> io::Result<()> {
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        while self.buf.len() < len && !self.eof {
+            let filled = self.buf.len();
+            self.buf.resize(filled + READ_CHUNK_SIZE, 0);
+            match self.reader.read(&mut self.buf[filled..]) {
+                Ok(n) => {
+                    self.buf.truncate(filled + n);
+                    self.eof = n == 0;
+                }
+                Err(e) => {
+                    self.buf.truncate(filled);
+                    if e.kind() != io::ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn error(&mut self, e: CliError) -> Option<Result<BlockEntry, CliError>> {
+        self.done = true;
+        Some(Err(e))
+    }
+}
+
+impl<R: Read> Iterator for BlocksReader<R> {
+    type Item = Result<BlockEntry, CliError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if let Err(e) = self.fill(END_MAGIC.len()) {
+            return self.error(CliError::io(&self.path, e));
+        }
+        let offset = self.offset;
+        let rest = &self.buf[self.pos..];
+        if rest.is_empty() {
+            self.done = true;
+            return None;
+        }
+        if rest.starts_with(&END_MAGIC) {
+            self.done = true;
+            self.end_magic = Some(offset);
+            return None;
+        }
+        if END_MAGIC.starts_with(rest) {
+            let e = format!("File ends in a truncated end magic ({:x?})", rest);
+            return self.error(CliError::serialization(&self.path, Some(offset), e));
+        }
+        // A block that fails to parse may just not be buffered in full yet,
+        // so the lookahead doubles up to the largest block there can be
+        loop {
+            let available = self.buf.len() - self.pos;
+            let mut i = 0;
+            match (self.parse)(&self.buf[self.pos..], &mut i) {
+                Ok(_) if i == 0 || i > available => {
+                    let e = format!("Block claims to end at byte {}", offset + i);
+                    return self.error(CliError::serialization(&self.path, Some(offset), e));
+                }
+                Ok(block) => {
+                    self.pos += i;
+                    self.offset += i;
+                    return Some(Ok(BlockEntry {
+                        block: *block,
+                        offset,
+                        end: offset + i,
+                    }));
+                }
+                Err(e) if self.eof => {
+                    return self.error(CliError::serialization(&self.path, Some(offset), e));
+                }
+                Err(e) if available >= self.max_block_size => {
+                    let e = format!("No block parses within {}B: {}", self.max_block_size, e);
+                    return self.error(CliError::serialization(&self.path, Some(offset), e));
+                }
+                Err(_) => {
+                    let len = (available * 2)
+                        .max(available + READ_CHUNK_SIZE)
+                        .min(self.max_block_size);
+                    if let Err(e) = self.fill(len) {
+                        return self.error(CliError::io(&self.path, e));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl BlocksWriter {
+    pub fn partial_path(path: &str) -> String {
+        format!("{}.partial", path)
+    }
+
+    /// Starts rewriting the blocks file at `path` from scratch.
+    pub fn create(path: &str) -> Result<BlocksWriter, CliError> {
+        let partial_path = BlocksWriter::partial_path(path);
+        let file = File::create(&partial_path).map_err(|e| CliError::io(&partial_path, e))?;
+        Ok(BlocksWriter {
+            path: path.to_string(),
+            partial_path,
+            writer: BufWriter::new(file),
+            len: 0,
+        })
+    }
+
+    /// Continues an earlier rewrite of `path`, discarding whatever was written
+    /// after its first `len` bytes.
+    pub fn resume(path: &str, len: usize) -> Result<BlocksWriter, CliError> {
+        let partial_path = BlocksWriter::partial_path(path);
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(&partial_path)
+            .map_err(|e| CliError::io(&partial_path, e))?;
+        let partial_len = file
+            .metadata()
+            .map_err(|e| CliError::io(&partial_path, e))?
+            .len();
+        if partial_len < len as u64 {
+            return Err(CliError::serialization(
+                &partial_path,
+                None,
+                format!("Expected at least {}B, file is {}B", len, partial_len),
+            ));
+        }
+        file.set_len(len as u64)
+            .and_then(|_| file.seek(SeekFrom::End(0)))
+            .map_err(|e| CliError::io(&partial_path, e))?;
+        Ok(BlocksWriter {
+            path: path.to_string(),
+            partial_path,
+            writer: BufWriter::new(file),
+            len,
+        })
+    }
+
+    /// Bytes written so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn write_block(&mut self, block: &Block) -> Result<Range<usize>, CliError> {
+        let start = self.len;
+        let serialized = serialize_block(block).map_err(|e| {
+            CliError::io(&self.partial_path, io::Error::new(io::ErrorKind::Other, e))
+        })?;
+        self.write_raw(&serialized)?;
+        Ok(start..self.len)
+    }
+
+    pub fn write_raw(&mut self, data: &[u8]) -> Result<(), CliError> {
+        self.writer
+            .write_all(data)
+            .map_err(|e| CliError::io(&self.partial_path, e))?;
+        self.len += data.len();
+        Ok(())
+    }
+
+    /// Makes everything written so far durable, so a rewrite can be resumed
+    /// from this point.
+    pub fn sync(&mut self) -> Result<(), CliError> {
+        self.writer
+            .flush()
+            .and_then(|_| self.writer.get_ref().sync_data())
+            .map_err(|e| CliError::io(&self.partial_path, e))
+    }
+
+    /// Replaces the blocks file with what has been written.
+    pub fn commit(mut self) -> Result<(), CliError> {
+        self.writer
+            .flush()
+            .and_then(|_| self.writer.get_ref().sync_all())
+            .and_then(|_| fs::rename(&self.partial_path, &self.path))
+            .map_err(|e| CliError::io(&self.partial_path, e))?;
+        sync_parent_dir(&self.path).map_err(|e| CliError::io(&self.path, e))
+    }
+}
+
+pub fn serialize_block(block: &Block) -> Result<Vec<u8>, String> {
+    let mut serialized = vec![0u8; block.serialized_len()];
+    block.serialize_into(&mut serialized, &mut 0)?;
+    Ok(serialized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use celestium::wallet::Wallet;
+    use tempfile::TempDir;
+
+    /// The first block of a freshly generated chain, serialized.
+    fn block_bytes() -> Vec<u8> {
+        let wallet = Wallet::generate_init_blockchain().unwrap();
+        let data = wallet.serialize_blockchain().unwrap();
+        let mut i = 0;
+        Block::from_serialized(&data, &mut i).unwrap();
+        data[..i].to_vec()
+    }
+
+    fn path_in(dir: &TempDir, name: &str) -> String {
+        dir.path().join(name).to_str().unwrap().to_string()
+    }
+
+    /// Offset of the serialization error `item` holds, failing on anything
+    /// else.
+    fn serialization_offset(item: Option<Result<BlockEntry, CliError>>) -> Option<usize> {
+        match item {
+            Some(Err(CliError::Serialization { offset, .. })) => offset,
+            Some(Err(e)) => panic!("Expected a serialization error, got {}", e),
+            Some(Ok(entry)) => panic!("Expected an error, read a block at {}", entry.offset),
+            None => panic!("Expected an error, the blocks ended"),
+        }
+    }
+
+    #[test]
+    fn reads_blocks_up_to_end_magic() {
+        let block = block_bytes();
+        let mut data = [&block[..], &block[..], &END_MAGIC[..]].concat();
+        data.extend_from_slice(b"trailer");
+        let mut blocks = BlocksReader::new("test", &data[..], 0);
+        let ranges: Vec<_> = blocks
+            .by_ref()
+            .map(|entry| entry.map(|entry| (entry.offset, entry.end)).unwrap())
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![(0, block.len()), (block.len(), 2 * block.len())]
+        );
+        assert_eq!(blocks.end_magic(), Some(2 * block.len()));
+    }
+
+    #[test]
+    fn block_cut_off_mid_serialization() {
+        let block = block_bytes();
+        let data = [&block[..], &block[..block.len() - 5]].concat();
+        let mut blocks = BlocksReader::new("test", &data[..], 0);
+        assert!(blocks.next().unwrap().is_ok());
+        assert_eq!(serialization_offset(blocks.next()), Some(block.len()));
+        assert!(blocks.next().is_none());
+    }
+
+    #[test]
+    fn parser_reading_past_buffer() {
+        fn overreaching_parse(data: &[u8], i: &mut usize) -> Result<Box<Block>, String> {
+            let block = Block::from_serialized(data, i)?;
+            *i = data.len() + 1;
+            Ok(block)
+        }
+        let block = block_bytes();
+        let data = [&block[..], &block[..]].concat();
+        let mut blocks = BlocksReader {
+            parse: overreaching_parse,
+            ..BlocksReader::new("test", &data[..], 0)
+        };
+        assert_eq!(serialization_offset(blocks.next()), Some(0));
+        assert!(blocks.next().is_none());
+    }
+
+    #[test]
+    fn corrupt_block_stops_lookahead() {
+        const CORRUPT: &[u8] = b"CORRUPT";
+        fn rejecting_parse(data: &[u8], i: &mut usize) -> Result<Box<Block>, String> {
+            if data.starts_with(CORRUPT) {
+                return Err("Corrupt block".to_string());
+            }
+            Block::from_serialized(data, i)
+        }
+        let block = block_bytes();
+        let max_block_size = 4 * READ_CHUNK_SIZE;
+        let mut data = [CORRUPT, &block[..]].concat();
+        while data.len() < 4 * max_block_size {
+            data.extend_from_slice(&block);
+        }
+        let mut unread = &data[..];
+        let mut blocks = BlocksReader {
+            parse: rejecting_parse,
+            max_block_size,
+            ..BlocksReader::new("test", &mut unread, 0)
+        };
+        assert_eq!(serialization_offset(blocks.next()), Some(0));
+        assert!(blocks.next().is_none());
+        drop(blocks);
+        assert!(data.len() - unread.len() <= max_block_size + READ_CHUNK_SIZE);
+    }
+
+    #[test]
+    fn partial_end_magic_at_eof() {
+        let block = block_bytes();
+        let data = [&block[..], &END_MAGIC[..2]].concat();
+        let mut blocks = BlocksReader::new("test", &data[..], 0);
+        assert!(blocks.next().unwrap().is_ok());
+        assert_eq!(serialization_offset(blocks.next()), Some(block.len()));
+        assert_eq!(blocks.end_magic(), None);
+    }
+
+    #[test]
+    fn append_keeps_garbage_after_end_magic() {
+        let dir = TempDir::new().unwrap();
+        let path = path_in(&dir, "blocks");
+        let block = block_bytes();
+        let garbage = [0xde, 0xad, 0xbe, 0xef, 0x41, 0x41];
+        fs::write(&path, [&block[..], &END_MAGIC[..], &garbage[..]].concat()).unwrap();
+        let blocks_file = BlocksFile::new(&path);
+        let appended = Block::from_serialized(&block, &mut 0).unwrap();
+        let range = blocks_file.append(&appended).unwrap();
+        assert_eq!(range, block.len()..2 * block.len());
+        assert_eq!(
+            fs::read(&path).unwrap(),
+            [&block[..], &block[..], &END_MAGIC[..], &garbage[..]].concat()
+        );
+        assert_eq!(blocks_file.end_magic().unwrap(), Some(2 * block.len()));
+        assert!(!std::path::Path::new(&BlocksWriter::partial_path(&path)).exists());
+    }
+
+    #[test]
+    fn blocks_from_past_eof() {
+        let dir = TempDir::new().unwrap();
+        let path = path_in(&dir, "blocks");
+        let block = block_bytes();
+        fs::write(&path, &block).unwrap();
+        let blocks_file = BlocksFile::new(&path);
+        assert!(blocks_file
+            .blocks_from(block.len())
+            .unwrap()
+            .next()
+            .is_none());
+        match blocks_file.blocks_from(block.len() + 1) {
+            Err(CliError::Serialization { offset, .. }) => {
+                assert_eq!(offset, Some(block.len() + 1))
+            }
+            Err(e) => panic!("Expected a serialization error, got {}", e),
+            Ok(_) => panic!("Expected an error"),
+        }
+    }
+
+    #[test]
+    fn resume_truncates_to_committed_bytes() {
+        let dir = TempDir::new().unwrap();
+        let path = path_in(&dir, "blocks");
+        let block = block_bytes();
+        let mut writer = BlocksWriter::create(&path).unwrap();
+        writer.write_raw(&block).unwrap();
+        writer.sync().unwrap();
+        let committed_bytes = writer.len();
+        // A block that was being written when the run died
+        writer.write_raw(&block[..block.len() / 2]).unwrap();
+        writer.sync().unwrap();
+        drop(writer);
+
+        let mut writer = BlocksWriter::resume(&path, committed_bytes).unwrap();
+        assert_eq!(writer.len(), committed_bytes);
+        let second = Block::from_serialized(&block, &mut 0).unwrap();
+        assert_eq!(
+            writer.write_block(&second).unwrap(),
+            block.len()..2 * block.len()
+        );
+        writer.commit().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), [&block[..], &block[..]].concat());
+    }
+
+    #[test]
+    fn resume_past_partial_end() {
+        let dir = TempDir::new().unwrap();
+        let path = path_in(&dir, "blocks");
+        let block = block_bytes();
+        let mut writer = BlocksWriter::create(&path).unwrap();
+        writer.write_raw(&block).unwrap();
+        writer.sync().unwrap();
+        drop(writer);
+        match BlocksWriter::resume(&path, block.len() + 1) {
+            Err(CliError::Serialization {
+                path: error_path,
+                offset,
+                ..
+            }) => {
+                assert_eq!(error_path, BlocksWriter::partial_path(&path));
+                assert_eq!(offset, None);
+            }
+            Err(e) => panic!("Expected a serialization error, got {}", e),
+            Ok(_) => panic!("Expected an error"),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{backup::Backups, data_dir::DataDir, error::CliError, output::Output};
+use colored::*;
+use serde_json::json;
+
+pub enum Options {
+    /// List the backups of a data dir
+    List {
+        /// Path to data dir
+        data: String,
+    },
+    /// Replace the wallet files of a data dir with those of a backup
+    Restore {
+        /// Path to data dir
+        data: String,
+        /// Backup to restore, as shown by `backup list`
+        id: String,
+        /// Newest backups to keep once the current wallet is backed up
+        keep: usize,
+    },
+    /// Delete all but the newest backups of a data dir
+    Prune {
+        /// Path to data dir
+        data: String,
+        /// Newest backups to keep
+        keep: usize,
+    },
+}
+
+pub fn run(options: &Options, output: &mut Output) -> Result<(), CliError> {
+    match options {
+        Options::List { data } => list(data, output),
+        Options::Restore { data, id, keep } => restore(data, id, *keep, output),
+        Options::Prune { data, keep } => {
+            let data_dir = DataDir::open(data)?;
+            prune(&Backups::new(&data_dir), *keep, output)
+        }
+    }
+}
+
+/// Deletes all but the newest `keep` backups, reporting each one deleted.
+pub fn prune(backups: &Backups, keep: usize, output: &mut Output) -> Result<(), CliError> {
+    for manifest in backups.prune(keep)? {
+        output.emit(
+            "backup_pruned",
+            json!({ "id": manifest.id, "created": manifest.created }),
+            format!("Deleted backup {}", manifest.id),
+        );
+    }
+    Ok(())
+}
+
+fn list(data: &str, output: &mut Output) -> Result<(), CliError> {
+    let data_dir = DataDir::open(data)?;
+    let backups = Backups::new(&data_dir);
+    let manifests = backups.list()?;
+    if manifests.is_empty() {
+        output.info(format!("No backups in {}", backups.path()));
+    }
+    for manifest in manifests {
+        let size: usize = manifest.files.values().map(|f| f.size).sum();
+        output.emit(
+            "backup",
+            json!({
+                "id": manifest.id,
+                "created": manifest.created,
+                "bytes": size,
+                "files": manifest.files,
+            }),
+            format!("{}  {}B", manifest.id, size),
+        );
+    }
+    Ok(())
+}
+
+fn restore(data: &str, id: &str, keep: usize, output: &mut Output) -> Result<(), CliError> {
+    let data_dir = DataDir::open(data)?;
+    let backups = Backups::new(&data_dir);
+    let bin_wallet = backups.load(id)?;
+    // Keep what is being replaced, unless there is no complete wallet to keep
+    if data_dir.missing_files().is_empty() {
+        let manifest = backups.create(&data_dir.load_binary()?)?;
+        output.emit(
+            "backup_created",
+            json!({ "id": manifest.id }),
+            format!("Backed up current wallet as {}", manifest.id),
+        );
+    }
+    data_dir.save_binary(&bin_wallet)?;
+    output.emit(
+        "restored",
+        json!({ "id": id, "path": data_dir.path() }),
+        format!("Restored {} to {}", id, data_dir.path())
+            .green()
+            .to_string(),
+    );
+    prune(&backups, keep, output)
+}
@@ -0,0 +1,48 @@
+use crate::{data_dir::DataDir, error::CliError, format_cel, output::Output};
+use secp256k1::PublicKey;
+use serde_json::json;
+
+pub struct Options {
+    /// Path to data dir
+    pub data: String,
+    /// Key to sum the unspent outputs of, the wallet's own if `None`
+    pub pk: Option<PublicKey>,
+}
+
+pub fn run(options: &Options, output: &mut Output) -> Result<(), CliError> {
+    let wallet = DataDir::open(&options.data)?.load()?;
+    let pk = match options.pk {
+        Some(pk) => pk,
+        None => wallet.get_pk().map_err(CliError::Wallet)?,
+    };
+    let mut balance = 0;
+    let mut values = 0;
+    let mut nfts = 0;
+    if let Some(outputs) = wallet.unspent_outputs.get(&pk) {
+        for transaction_output in outputs.values() {
+            match transaction_output.value.get_value() {
+                Ok(value) => {
+                    balance += value;
+                    values += 1;
+                }
+                Err(_) => nfts += 1,
+            }
+        }
+    }
+    output.emit(
+        "balance",
+        json!({
+            "pk": hex::encode(&pk.serialize()[..]),
+            "balance_cel": format_cel(balance),
+            "value_outputs": values,
+            "nfts": nfts,
+        }),
+        format!(
+            "{} CEL in {} unspent outputs, {} NFTs",
+            format_cel(balance),
+            values,
+            nfts
+        ),
+    );
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use crate::{blocks_file::BlocksReader, error::CliError, output::Output, write_atomically};
+use celestium::{
+    block::Block, block_hash::BlockHash, serialize::Serialize, transaction::Transaction,
+    transaction_output::TransactionOutput, transaction_value::TransactionValue, wallet::Wallet,
+};
+use colored::*;
+use rand::{thread_rng, Rng};
+use rayon::ThreadPoolBuilder;
+use secp256k1::{PublicKey, Secp256k1};
+use serde::Serialize as SerdeSerialize;
+use serde_json::json;
+use sha3::{Digest, Sha3_256};
+use std::time::Instant;
+
+pub struct Options {
+    /// Thread counts to try
+    pub threads: Vec<u64>,
+    /// Par-work sizes to try, each with every thread count
+    pub par_work: Vec<u64>,
+    /// Blocks and transactions to mine per configuration
+    pub samples: usize,
+    /// Path to save results as JSON to
+    pub output: Option<String>,
+}
+
+/// Time-to-solution distribution in milliseconds.
+#[derive(SerdeSerialize, Debug)]
+pub struct Percentiles {
+    pub min: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+#[derive(SerdeSerialize, Debug)]
+pub struct BenchResult {
+    pub threads: u64,
+    pub par_work: u64,
+    /// Estimated from the expected number of hashes per mined block, `None`
+    /// if the difficulty could not be determined
+    pub hashes_per_sec: Option<f64>,
+    pub block_ms: Percentiles,
+    pub transaction_ms: Percentiles,
+}
+
+impl Percentiles {
+    fn from_samples(mut samples: Vec<f64>) -> Percentiles {
+        samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        // Nearest-rank percentile
+        let at = |p: f64| {
+            let rank = (p / 100.0 * samples.len() as f64).ceil() as usize;
+            samples[rank.max(1) - 1]
+        };
+        Percentiles {
+            min: samples[0],
+            p50: at(50.0),
+            p90: at(90.0),
+            p99: at(99.0),
+            max: samples[samples.len() - 1],
+            mean: samples.iter().sum::<f64>() / samples.len() as f64,
+        }
+    }
+}
+
+/// Leading zero bits a block hash needs to contain enough work, found by
+/// probing `BlockHash::contains_enough_work` with hashes that are all ones
+/// after a growing run of zeros.
+fn required_zero_bits() -> Option<u32> {
+    (0..=256).find(|&bits| {
+        let mut hash = [0xffu8; 32];
+        for bit in 0..bits as usize {
+            hash[bit / 8] &= !(0x80 >> (bit % 8));
+        }
+        BlockHash::contains_enough_work(&hash)
+    })
+}
+
+fn random_block_hash() -> Result<BlockHash, CliError> {
+    let mut hash = [0u8; 32];
+    thread_rng().fill(&mut hash[..]);
+    Ok(*BlockHash::from_serialized(&hash, &mut 0).map_err(CliError::Wallet)?)
+}
+
+/// An unmined pixel-style base transaction with random contents.
+fn random_transaction(pk: PublicKey) -> Result<Transaction, CliError> {
+    let mut message = [0u8; 33];
+    thread_rng().fill(&mut message[..]);
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&Sha3_256::digest(&message));
+    Transaction::new_id_base_transaction(
+        random_block_hash()?,
+        message,
+        TransactionOutput::new(
+            TransactionValue::new_id_transfer(hash).map_err(CliError::Wallet)?,
+            pk,
+        ),
+    )
+    .map_err(CliError::Wallet)
+}
+
+pub fn run(options: &Options, output: &mut Output) -> Result<(), CliError> {
+    output.info("Generating synthetic blockchain...");
+    let wallet = Wallet::generate_init_blockchain().map_err(CliError::Wallet)?;
+    let serialized_blocks = wallet.serialize_blockchain().map_err(CliError::Wallet)?;
+    let template: Block = BlocksReader::new("<generated>", &serialized_blocks[..], 0)
+        .next()
+        .ok_or_else(|| CliError::Wallet("Generated blockchain has no blocks".to_string()))??
+        .block;
+    let sk = wallet.get_sk().map_err(CliError::Wallet)?;
+    let pk = PublicKey::from_secret_key(&Secp256k1::new(), &sk);
+    let wallet = Wallet::new(pk, sk).map_err(CliError::Wallet)?;
+
+    let zero_bits = required_zero_bits();
+    match zero_bits {
+        Some(bits) => output.info(format!(
+            "Blocks need {} leading zero bits, ~{} hashes each",
+            bits,
+            2f64.powi(bits as i32)
+        )),
+        None => output.info("Could not determine difficulty, hash rates are unavailable"),
+    }
+
+    let mut results = Vec::new();
+    for &par_work in &options.par_work {
+        for &threads in &options.threads {
+            let thread_pool = ThreadPoolBuilder::new()
+                .num_threads(threads as usize)
+                .build()
+                .map_err(|e| CliError::System(format!("Could not start threads. {}", e)))?;
+            let mut block_ms = Vec::with_capacity(options.samples);
+            let mut transaction_ms = Vec::with_capacity(options.samples);
+            for _ in 0..options.samples {
+                // A fresh back hash makes every sample a different puzzle
+                let mut block = template.clone();
+                block.back_hash = random_block_hash()?;
+                let start = Instant::now();
+                wallet
+                    .mine_block(threads, par_work, block)
+                    .map_err(CliError::Wallet)?;
+                block_ms.push(start.elapsed().as_secs_f64() * 1000.0);
+
+                let transaction = random_transaction(pk)?;
+                let start = Instant::now();
+                Wallet::mine_transaction(threads, par_work, transaction, &thread_pool)
+                    .map_err(CliError::Wallet)?;
+                transaction_ms.push(start.elapsed().as_secs_f64() * 1000.0);
+            }
+            let hashes_per_sec = zero_bits.map(|bits| {
+                block_ms.len() as f64 * 2f64.powi(bits as i32)
+                    / (block_ms.iter().sum::<f64>() / 1000.0)
+            });
+            let result = BenchResult {
+                threads,
+                par_work,
+                hashes_per_sec,
+                block_ms: Percentiles::from_samples(block_ms),
+                transaction_ms: Percentiles::from_samples(transaction_ms),
+            };
+            output.emit(
+                "result",
+                json!(result),
+                format!(
+                    "threads {:>3}  par-work {:>9}  {:>12} H/s  block p50 {:>9.1}ms p90 {:>9.1}ms p99 {:>9.1}ms  transaction p50 {:>9.1}ms",
+                    threads,
+                    par_work,
+                    hashes_per_sec.map_or("-".to_string(), |h| format!("{:.0}", h)),
+                    result.block_ms.p50,
+                    result.block_ms.p90,
+                    result.block_ms.p99,
+                    result.transaction_ms.p50,
+                ),
+            );
+            results.push(result);
+        }
+    }
+
+    // Fastest by hash rate, or by mean time per block if that is unknown
+    let best = results
+        .iter()
+        .min_by(|a, b| {
+            let score = |r: &BenchResult| r.hashes_per_sec.map_or(r.block_ms.mean, |h| -h);
+            score(a).partial_cmp(&score(b)).unwrap()
+        })
+        .ok_or_else(|| CliError::Usage("No configurations to benchmark".to_string()))?;
+    output.emit(
+        "best",
+        json!(best),
+        format!(
+            "Best: --threads {} --par-work {}",
+            best.threads, best.par_work
+        )
+        .green()
+        .to_string(),
+    );
+
+    if let Some(path) = &options.output {
+        let report = json!({
+            "zero_bits": zero_bits,
+            "samples": options.samples,
+            "results": results,
+            "best": best,
+        });
+        let data = serde_json::to_vec_pretty(&report)
+            .map_err(|e| CliError::serialization(path, None, e))?;
+        write_atomically(path, &data).map_err(|e| CliError::io(path, e))?;
+        output.emit(
+            "saved",
+            json!({ "path": path }),
+            format!("Saved results to {:?}", path),
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,439 @@
+use crate::{
+    backup::Backups,
+    commands::backup,
+    data_dir::{wallet_files, DataDir, LoadOptions},
+    error::CliError,
+    format_cel,
+    key_file::Passphrase,
+    output::Output,
+};
+use celestium::{block::Block, serialize::Serialize, transaction::Transaction, wallet::Wallet};
+use colored::*;
+use secp256k1::PublicKey;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    mem,
+    time::Instant,
+};
+
+pub struct Options {
+    /// Path to data dir
+    pub data: String,
+    /// How to decode the wallet
+    pub load: LoadOptions,
+    /// Skip backing up the data dir before changing it
+    pub no_backup: bool,
+    /// Newest backups to keep, older ones are deleted after backing up
+    pub keep_backups: usize,
+    /// Where to get the passphrase of an encrypted `sk` file from
+    pub passphrase: Passphrase,
+    /// Only report the blocks that would be created, writing nothing
+    pub dry_run: bool,
+    /// Which off chain transactions to collect and how to split them up
+    pub selection: Selection,
+    /// Mine each block before adding it, not done for a dry run
+    pub mine: bool,
+    /// Number of threads to mine with
+    pub threads: u64,
+    /// Amount of work handed to each thread at a time
+    pub par_work: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TransactionKind {
+    /// Base transactions setting a pixel
+    Pixel,
+    /// Transactions spending outputs
+    Transfer,
+}
+
+/// Picks the off chain transactions to put into blocks, in the order they
+/// were added. Transactions that are not picked stay off chain, and so does
+/// every transaction spending an output of one that stays off chain.
+#[derive(Default)]
+pub struct Selection {
+    /// Only collect transactions of this kind
+    pub kind: Option<TransactionKind>,
+    /// Only collect transactions paying to, or spending outputs of, one of
+    /// these keys
+    pub pks: Vec<PublicKey>,
+    /// Leave this many of the most recently added transactions off chain.
+    /// Off chain transactions carry no timestamp, so age is their position.
+    pub skip_newest: usize,
+    /// Most transactions per block
+    pub max_transactions: Option<usize>,
+    /// Most serialized transaction bytes per block
+    pub max_bytes: Option<usize>,
+    /// Most blocks to create, unlimited if `None`
+    pub max_blocks: Option<usize>,
+}
+
+impl Selection {
+    fn matches(&self, wallet: &Wallet, transaction: &Transaction) -> Result<bool, CliError> {
+        let kind = if transaction.get_base_transaction_message().is_ok() {
+            TransactionKind::Pixel
+        } else {
+            TransactionKind::Transfer
+        };
+        if self.kind.map_or(false, |k| k != kind) {
+            return Ok(false);
+        }
+        if self.pks.is_empty()
+            || transaction
+                .get_outputs()
+                .iter()
+                .any(|output| self.pks.contains(&output.pk))
+        {
+            return Ok(true);
+        }
+        for input in transaction.get_inputs() {
+            let spent_output = wallet
+                .get_transaction(&input.block_hash, &input.transaction_hash)
+                .map_err(CliError::Wallet)?
+                .get_output(&input.output_index);
+            if self.pks.contains(&spent_output.pk) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Reports what committing `block` with `transactions` would do: how many of
+/// them are pixel base transactions and how many transfers, the value the
+/// transfers pay out besides change, and the NFTs that change owner.
+fn preview(
+    wallet: &Wallet,
+    block: &Block,
+    transactions: &[Transaction],
+    output: &mut Output,
+) -> Result<(), CliError> {
+    let mut pixels = 0;
+    let mut transfers = 0;
+    let mut value_moved = 0;
+    let mut nfts = Vec::new();
+    for transaction in transactions {
+        if transaction.get_base_transaction_message().is_ok() {
+            pixels += 1;
+            continue;
+        }
+        transfers += 1;
+        let mut senders = Vec::new();
+        let mut previous_owners = HashMap::new();
+        for input in transaction.get_inputs() {
+            let spent_output = wallet
+                .get_transaction(&input.block_hash, &input.transaction_hash)
+                .map_err(CliError::Wallet)?
+                .get_output(&input.output_index);
+            if spent_output.value.is_id_transfer() {
+                if let Ok(id) = spent_output.value.get_id() {
+                    previous_owners.insert(id, spent_output.pk);
+                }
+            }
+            senders.push(spent_output.pk);
+        }
+        for transaction_output in transaction.get_outputs() {
+            if transaction_output.value.is_id_transfer() {
+                let id = transaction_output
+                    .value
+                    .get_id()
+                    .map_err(CliError::Wallet)?;
+                nfts.push(json!({
+                    "id": hex::encode(id),
+                    "from": previous_owners.get(&id).map(|pk| hex::encode(&pk.serialize()[..])),
+                    "to": hex::encode(&transaction_output.pk.serialize()[..]),
+                }));
+            } else if !senders.contains(&transaction_output.pk) {
+                if let Ok(value) = transaction_output.value.get_value() {
+                    value_moved += value;
+                }
+            }
+        }
+    }
+
+    let mut text = format!(
+        "Block {} would be created from {} transactions\n\
+         Pixel base transactions: {}\n\
+         Value transfers: {}\n\
+         Total value moved: {} CEL\n\
+         NFTs changing hands: {}",
+        block.hash(),
+        transactions.len(),
+        pixels,
+        transfers,
+        format_cel(value_moved),
+        nfts.len()
+    );
+    for nft in &nfts {
+        text.push_str(&format!(
+            "\n  {} {} -> {}",
+            nft["id"].as_str().unwrap_or_default(),
+            nft["from"].as_str().unwrap_or("?"),
+            nft["to"].as_str().unwrap_or_default()
+        ));
+    }
+    output.emit(
+        "preview",
+        json!({
+            "hash": hex::encode(block.hash().hash()),
+            "transactions": transactions.len(),
+            "pixel_transactions": pixels,
+            "transfer_transactions": transfers,
+            "value_moved_cel": format_cel(value_moved),
+            "nfts": nfts,
+        }),
+        text,
+    );
+    Ok(())
+}
+
+pub fn run(options: &Options, output: &mut Output) -> Result<(), CliError> {
+    // A dry run writes nothing, not even to finish an interrupted save
+    let mut data_dir = if options.dry_run {
+        DataDir::open_read_only(&options.data)?
+    } else {
+        DataDir::open(&options.data)?
+    };
+    data_dir.set_passphrase(options.passphrase.clone());
+    if let Some(recovery) = data_dir.recovery() {
+        output.emit(
+            "recovered",
+            json!({ "path": data_dir.path(), "recovery": format!("{:?}", recovery) }),
+            recovery.to_string(),
+        );
+    }
+
+    let backups = Backups::new(&data_dir);
+    output.info("Loading binary wallet...");
+    let bin_wallet = &data_dir.load_binary()?;
+    output.info("Binary wallet loaded!");
+    for (name, data) in wallet_files(bin_wallet).iter() {
+        output.info(format!("{}: {}", name, data.len()));
+    }
+    if !options.no_backup && !options.dry_run {
+        let manifest = backups.create(bin_wallet)?;
+        output.emit(
+            "backup_created",
+            json!({ "id": manifest.id }),
+            format!("Backed up wallet as {}", manifest.id),
+        );
+        backup::prune(&backups, options.keep_backups, output)?;
+    }
+    output.info("Loading wallet...");
+    let mut wallet = data_dir.wallet_from_binary(
+        bin_wallet,
+        LoadOptions {
+            // The preview of a dry run signs nothing
+            need_sk: !options.dry_run,
+            ..options.load
+        },
+    )?;
+    output.info(format!(
+        "Wallet loaded! {} | {}",
+        wallet.count_blocks(),
+        wallet.on_chain_transactions.len()
+    ));
+
+    // Matched while the wallet still holds every off chain transaction, so
+    // inputs spending one of them can be looked up
+    let mut matches = Vec::with_capacity(wallet.off_chain_transactions.len());
+    for (_, transaction) in &wallet.off_chain_transactions {
+        matches.push(options.selection.matches(&wallet, transaction)?);
+    }
+    // Ages are taken from positions: this relies on the wallet keeping its
+    // off chain transactions in insertion order, oldest first
+    let keep = matches.len().saturating_sub(options.selection.skip_newest);
+    let hashes: Vec<_> = wallet
+        .off_chain_transactions
+        .values()
+        .map(|transaction| transaction.hash().hash())
+        .collect();
+    let positions: HashMap<_, _> = hashes.iter().enumerate().map(|(i, h)| (*h, i)).collect();
+    // Off chain transactions each one spends outputs of, by position
+    let parents: Vec<Vec<usize>> = wallet
+        .off_chain_transactions
+        .values()
+        .map(|transaction| {
+            transaction
+                .get_inputs()
+                .iter()
+                .filter_map(|input| positions.get(&input.transaction_hash.hash()).copied())
+                .collect()
+        })
+        .collect();
+    // Whether each off chain transaction has been put into a block
+    let mut collected = vec![false; hashes.len()];
+    // Leftovers go back to the off chain transactions once the blocks are made
+    let mut selected = Vec::new();
+    let mut leftover = Vec::new();
+    for (i, entry) in mem::take(&mut wallet.off_chain_transactions)
+        .into_iter()
+        .enumerate()
+    {
+        if i < keep && matches[i] {
+            selected.push((i, entry));
+        } else {
+            leftover.push((i, entry));
+        }
+    }
+    let mut selected = selected.into_iter().peekable();
+    let mut blocks = 0;
+    while selected.peek().is_some()
+        && options
+            .selection
+            .max_blocks
+            .map_or(true, |max| blocks < max)
+    {
+        let mut batch = Vec::new();
+        let mut batch_bytes = 0;
+        while let Some((i, (_, transaction))) = selected.peek() {
+            let i = *i;
+            // A transaction can only go on chain along with or after the
+            // transactions it spends, if any of those stays off chain so does
+            // this one
+            if parents[i].iter().any(|&parent| !collected[parent]) {
+                output.emit(
+                    "held_back",
+                    json!({ "hash": hex::encode(hashes[i]) }),
+                    format!(
+                        "Leaving off chain transaction {}, it spends a transaction that is not collected",
+                        hex::encode(hashes[i])
+                    ),
+                );
+                leftover.extend(selected.next());
+                continue;
+            }
+            let len = transaction.serialized_len();
+            if options.selection.max_bytes.map_or(false, |max| len > max) {
+                output.emit(
+                    "oversized",
+                    json!({ "bytes": len }),
+                    format!(
+                        "Leaving off chain a {} byte transaction larger than the block limit",
+                        len
+                    ),
+                );
+                leftover.extend(selected.next());
+                continue;
+            }
+            if options
+                .selection
+                .max_transactions
+                .map_or(false, |max| batch.len() >= max)
+                || options
+                    .selection
+                    .max_bytes
+                    .map_or(false, |max| batch_bytes + len > max)
+            {
+                break;
+            }
+            batch_bytes += len;
+            collected[i] = true;
+            batch.extend(selected.next());
+        }
+        if batch.is_empty() {
+            break;
+        }
+        let batch_len = batch.len();
+        wallet.off_chain_transactions = batch.into_iter().map(|(_, entry)| entry).collect();
+
+        let (mut block, transactions) = wallet
+            .mining_data_from_off_chain_transactions()
+            .map_err(CliError::Wallet)?;
+
+        if options.dry_run {
+            preview(&wallet, &block, &transactions, output)?;
+        } else {
+            if options.mine {
+                if output.is_text() {
+                    print!("Mining block {}", blocks + 1);
+                    io::stdout()
+                        .flush()
+                        .map_err(|e| CliError::io("<stdout>", e))?;
+                }
+                let start = Instant::now();
+                block = wallet
+                    .mine_block(options.threads, options.par_work, block)
+                    .map_err(|e| CliError::Wallet(format!("Could not mine block. {}", e)))?;
+                let elapsed = start.elapsed();
+                output.emit(
+                    "mined",
+                    json!({
+                        "hash": hex::encode(block.hash().hash()),
+                        "elapsed_ms": elapsed.as_millis() as u64,
+                    }),
+                    format!("{}\nTime: {:?}", ". Done ✔️".green(), elapsed),
+                );
+            }
+            output.emit(
+                "block_created",
+                json!({
+                    "hash": hex::encode(block.hash().hash()),
+                    "transactions": transactions.len(),
+                    "bytes": batch_bytes,
+                }),
+                format!(
+                    "Block {} created from {} transactions",
+                    block.hash(),
+                    transactions.len()
+                ),
+            );
+        }
+
+        wallet
+            .add_on_chain_transactions(transactions, block.hash(), block.transactions_hash().hash())
+            .map_err(CliError::Wallet)?;
+
+        if !wallet.off_chain_transactions.is_empty() {
+            return Err(CliError::Wallet(format!(
+                "{} of {} selected off chain transactions still in wallet after collecting them",
+                wallet.off_chain_transactions.len(),
+                batch_len
+            )));
+        }
+
+        wallet.add_block(block).map_err(CliError::Wallet)?;
+        blocks += 1;
+    }
+    leftover.extend(selected);
+    // Transactions are held back above unless everything they spend is
+    // collected first
+    debug_assert!((0..hashes.len())
+        .all(|i| !collected[i] || parents[i].iter().all(|&parent| collected[parent])));
+    leftover.sort_by_key(|(i, _)| *i);
+    wallet.off_chain_transactions = leftover.into_iter().map(|(_, entry)| entry).collect();
+    output.emit(
+        "collected",
+        json!({
+            "blocks": blocks,
+            "off_chain_transactions": wallet.off_chain_transactions.len(),
+        }),
+        format!(
+            "Created {} blocks, {} transactions left off chain",
+            blocks,
+            wallet.off_chain_transactions.len()
+        ),
+    );
+    if options.dry_run {
+        return Ok(());
+    }
+
+    output.info(format!(
+        "Wallet loaded! {} | {}",
+        wallet.count_blocks(),
+        wallet.on_chain_transactions.len()
+    ));
+    data_dir.save(&wallet)?;
+    output.emit(
+        "saved",
+        json!({
+            "blocks": wallet.count_blocks(),
+            "on_chain_transactions": wallet.on_chain_transactions.len(),
+            "path": data_dir.path(),
+        }),
+        format!("Saved wallet to {}", data_dir.path()),
+    );
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use crate::{config::Config, error::CliError, output::Output};
+use serde_json::json;
+
+pub struct Options {
+    /// Config file given with `--config`
+    pub path: Option<String>,
+}
+
+/// Prints the effective config, with defaults filled in, merged from the
+/// layers that could be read. Layers that could not are listed and the first
+/// of them fails the command once everything else is printed.
+pub fn show(options: &Options, output: &mut Output) -> Result<(), CliError> {
+    let mut config = Config::default();
+    let mut sources = Vec::new();
+    let mut header = String::new();
+    let mut first_error = None;
+    for layer in Config::load_layers(options.path.as_deref()) {
+        match layer.config {
+            None => {}
+            Some(Ok(layer_config)) => {
+                config = config.merge(layer_config);
+                header.push_str(&format!("# {}\n", layer.source));
+                sources.push(layer.source);
+            }
+            Some(Err(e)) => {
+                header.push_str(&format!("# {}, skipped: {}\n", layer.source, e));
+                output.record(
+                    "invalid_layer",
+                    json!({ "source": layer.source, "error": e.to_string() }),
+                );
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    let effective = config.effective();
+    let text = toml::to_string(&effective).map_err(|e| CliError::Usage(e.to_string()))?;
+    output.emit(
+        "config",
+        json!({ "config": effective, "sources": sources }),
+        format!(
+            "# Merged from, in increasing precedence:\n{}\n{}",
+            header, text
+        ),
+    );
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Lists every config layer looked for, lowest precedence first, and whether
+/// it exists and could be read.
+pub fn path(options: &Options, output: &mut Output) -> Result<(), CliError> {
+    for layer in Config::load_layers(options.path.as_deref()) {
+        let (status, error) = match &layer.config {
+            None => ("missing", None),
+            Some(Ok(_)) => ("ok", None),
+            Some(Err(e)) => ("invalid", Some(e.to_string())),
+        };
+        output.emit(
+            "layer",
+            json!({ "source": layer.source, "status": status, "error": error }),
+            match &error {
+                Some(error) => format!("{}: {}, {}", layer.source, status, error),
+                None => format!("{}: {}", layer.source, status),
+            },
+        );
+    }
+    Ok(())
+}
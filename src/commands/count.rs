@@ -0,0 +1,146 @@
+use crate::{
+    data_dir::{wallet_files, DataDir, LoadOptions},
+    error::CliError,
+    format_cel,
+    output::Output,
+    pixel::{calc_pixel_hash, NUM_COLORS, PIXEL_HASH_SIZE},
+};
+use serde_json::json;
+use sha3::{Digest, Sha3_224};
+use std::collections::HashMap;
+
+pub struct Options {
+    /// Path to data dir
+    pub data: String,
+    /// How to decode the wallet
+    pub load: LoadOptions,
+}
+
+pub fn run(options: &Options, output: &mut Output) -> Result<(), CliError> {
+    let data_dir = DataDir::open(&options.data)?;
+    if let Some(recovery) = data_dir.recovery() {
+        output.emit(
+            "recovered",
+            json!({ "path": data_dir.path(), "recovery": format!("{:?}", recovery) }),
+            recovery.to_string(),
+        );
+    }
+
+    output.info("Loading binary wallet...");
+    let bin_wallet = &data_dir.load_binary()?;
+    output.info("Binary wallet loaded!");
+    for (name, data) in wallet_files(bin_wallet).iter() {
+        output.info(format!("{}: {}", name, data.len()));
+    }
+    output.info("Loading wallet...");
+    let wallet = data_dir.wallet_from_binary(bin_wallet, options.load)?;
+    output.info("Wallet loaded!");
+
+    let pb = output.progress_bar(
+        wallet.off_chain_transactions.len() as u64,
+        "Loading candidates from off chain transactions",
+    );
+    let mut candidates: HashMap<(u16, u16), HashMap<[u8; PIXEL_HASH_SIZE], [u8; PIXEL_HASH_SIZE]>> =
+        HashMap::new();
+    let pk = wallet.get_pk().map_err(CliError::Wallet)?;
+    let mut total_value_spent = 0;
+    let mut total_things_bought = 0;
+    for (_, transaction) in &wallet.off_chain_transactions {
+        pb.inc(1);
+        if let Ok(base_message) = transaction.get_base_transaction_message() {
+            let mut back_hash: [u8; PIXEL_HASH_SIZE] = [0u8; PIXEL_HASH_SIZE];
+            back_hash.copy_from_slice(&base_message[..PIXEL_HASH_SIZE]);
+            let x: u16 = ((base_message[PIXEL_HASH_SIZE] as u16) << 8)
+                + (base_message[PIXEL_HASH_SIZE + 1] as u16);
+            let y: u16 = ((base_message[PIXEL_HASH_SIZE + 2] as u16) << 8)
+                + (base_message[PIXEL_HASH_SIZE + 3] as u16);
+            let color: u8 = base_message[PIXEL_HASH_SIZE + 4];
+            if x < 1000 && y < 1000 && color < NUM_COLORS {
+                let a = candidates
+                    .entry((x as u16, y as u16))
+                    .or_insert_with(HashMap::new);
+                a.insert(calc_pixel_hash(x, y, color, back_hash), back_hash);
+            }
+        } else {
+            let mut things_bought = 0;
+            for input in transaction.get_inputs() {
+                let spent_output = wallet
+                    .get_transaction(&input.block_hash, &input.transaction_hash)
+                    .map_err(CliError::Wallet)?
+                    .get_output(&input.output_index);
+                if spent_output.value.is_id_transfer() && spent_output.pk == pk {
+                    things_bought += 1;
+                }
+            }
+            if things_bought > 0 {
+                total_things_bought += things_bought;
+                for transaction_output in transaction.get_outputs() {
+                    if transaction_output.pk == pk {
+                        if let Ok(value) = transaction_output.value.get_value() {
+                            total_value_spent += value;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    pb.finish();
+
+    let pb = output.progress_bar(candidates.len() as u64, "Processing candidates");
+    let mut total_set_pixels_unique = 0;
+    let mut total_set_pixels = 0;
+    for ((x, y), candidate) in candidates.iter() {
+        pb.inc(1);
+        let mut longest_candidate = (None, 0);
+        let mut to_digest = [0u8; 33];
+        to_digest[PIXEL_HASH_SIZE] = (x >> 8) as u8;
+        to_digest[PIXEL_HASH_SIZE + 1] = (x & 0xff) as u8;
+        to_digest[PIXEL_HASH_SIZE + 2] = (y >> 8) as u8;
+        to_digest[PIXEL_HASH_SIZE + 3] = (y & 0xff) as u8;
+        to_digest[PIXEL_HASH_SIZE + 4] = 7u8;
+        let mut init_hash = [0u8; PIXEL_HASH_SIZE];
+        init_hash.copy_from_slice(&Sha3_224::digest(&to_digest));
+        for (hash, back_hash) in candidate {
+            let mut len = 1;
+            let mut back_item = back_hash;
+            while let Some(tmp_back_item) = candidate.get(back_item) {
+                back_item = tmp_back_item;
+                len += 1;
+            }
+            if len > longest_candidate.1 && *back_item == init_hash {
+                longest_candidate = (Some(hash), len);
+            }
+        }
+
+        if let (Some(_), len) = longest_candidate {
+            total_set_pixels_unique += 1;
+            total_set_pixels += len;
+        }
+    }
+    pb.finish();
+
+    let total_value_spent = format_cel(total_value_spent);
+    output.emit(
+        "statistics",
+        json!({
+            "things_bought": total_things_bought,
+            "value_spent_cel": total_value_spent,
+            "unique_pixels": total_set_pixels_unique,
+            "total_pixels": total_set_pixels,
+            "unique_users": wallet.unspent_outputs.len(),
+        }),
+        format!(
+            "------------------------------------------------------\n\
+             Total things bought from us: {}\n\
+             Total value spent on us: {}\n\
+             Found {} unique pixels out of {} total set\n\
+             {} unique users have interacted with the blockchain",
+            total_things_bought,
+            total_value_spent,
+            total_set_pixels_unique,
+            total_set_pixels,
+            wallet.unspent_outputs.len()
+        ),
+    );
+    Ok(())
+}
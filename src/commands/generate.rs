@@ -0,0 +1,60 @@
+use crate::{
+    blocks_file::BlocksWriter,
+    data_dir::DataDir,
+    error::CliError,
+    key_file::{self, Passphrase},
+    output::Output,
+};
+use celestium::wallet::Wallet;
+use serde_json::json;
+
+pub struct Options {
+    /// Path to save binary blocks file to
+    pub blocks: String,
+    /// Path to save secret key file to
+    pub sk: String,
+    /// Encrypt the secret key with a passphrase read from here
+    pub encrypt: Option<Passphrase>,
+    /// Path to create a data dir holding the generated wallet in
+    pub data: Option<String>,
+}
+
+pub fn run(options: &Options, output: &mut Output) -> Result<(), CliError> {
+    let wallet = Wallet::generate_init_blockchain().map_err(CliError::Wallet)?;
+    output.info(format!(
+        "Generated {} blocks, serializing",
+        wallet.count_blocks()
+    ));
+    let serialized_blocks = wallet.serialize_blockchain().map_err(CliError::Wallet)?;
+    let sk = wallet.get_sk().map_err(CliError::Wallet)?;
+    let blocks_path = &options.blocks;
+    let sk_path = &options.sk;
+    let passphrase = options
+        .encrypt
+        .as_ref()
+        .map(Passphrase::read_new)
+        .transpose()?;
+    if let Some(data) = &options.data {
+        let mut bin_wallet = wallet.to_binary().map_err(CliError::Wallet)?;
+        if let Some(passphrase) = &passphrase {
+            bin_wallet.sk_bin = key_file::encrypt(&sk, passphrase)
+                .map_err(|e| CliError::serialization(&format!("{}/sk", data), None, e))?;
+        }
+        DataDir::create(data, &bin_wallet)?;
+    }
+    let mut blocks_writer = BlocksWriter::create(blocks_path)?;
+    blocks_writer.write_raw(&serialized_blocks)?;
+    blocks_writer.commit()?;
+    key_file::write(sk_path, &sk, passphrase.as_deref())?;
+    output.emit(
+        "generated",
+        json!({
+            "blocks": wallet.count_blocks(),
+            "blocks_path": blocks_path,
+            "sk_path": sk_path,
+            "data": options.data,
+        }),
+        "Done.",
+    );
+    Ok(())
+}
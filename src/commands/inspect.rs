@@ -0,0 +1,265 @@
+use crate::{
+    blocks_file::{BlockEntry, BlocksFile},
+    data_dir::DataDir,
+    error::CliError,
+    format_cel,
+    output::Output,
+    pixel::{Pixel, COLOR_MAP},
+    read_file,
+};
+use celestium::{
+    block_hash::BlockHash, serialize::Serialize, transaction::Transaction, wallet::Wallet,
+};
+use serde_json::{json, Value};
+
+pub enum Source {
+    /// Binary blocks file, with the blocks' transactions if a data dir is given
+    Blocks { path: String, data: Option<String> },
+    /// File of serialized transactions, one after the other
+    Transactions { path: String },
+    /// Off chain transactions of a data dir
+    OffChain { data: String },
+}
+
+/// Picks the items to show, all of them if no selector is given.
+pub enum Selector {
+    /// Position of the item, counting from 1
+    Index(usize),
+    Hash([u8; 32]),
+}
+
+pub struct Options {
+    pub source: Source,
+    pub selector: Option<Selector>,
+}
+
+impl Selector {
+    /// Parses a 64 digit hex hash or a decimal index.
+    pub fn parse(selector: &str) -> Result<Selector, CliError> {
+        if selector.len() == 64 {
+            let mut hash = [0u8; 32];
+            hex::decode_to_slice(selector, &mut hash)
+                .map_err(|e| CliError::Usage(format!("Invalid hash \"{}\": {}", selector, e)))?;
+            return Ok(Selector::Hash(hash));
+        }
+        match selector.parse() {
+            Ok(0) | Err(_) => Err(CliError::Usage(format!(
+                "Invalid selector \"{}\", expected an index from 1 or a hash",
+                selector
+            ))),
+            Ok(index) => Ok(Selector::Index(index)),
+        }
+    }
+
+    fn matches(&self, index: usize, hash: [u8; 32]) -> bool {
+        match self {
+            Selector::Index(i) => *i == index,
+            Selector::Hash(h) => *h == hash,
+        }
+    }
+}
+
+/// Describes `transaction` as a tree of its hash, inputs, outputs and, for a
+/// base transaction, its message and the pixel it sets.
+pub fn describe_transaction(transaction: &Transaction) -> Value {
+    let base = transaction
+        .get_base_transaction_message()
+        .ok()
+        .map(|message| {
+            let pixel = Pixel::from_base_message(&message[..]).map(|pixel| {
+                let [r, g, b, _] = COLOR_MAP[pixel.color as usize];
+                json!({
+                    "x": pixel.x,
+                    "y": pixel.y,
+                    "color": pixel.color,
+                    "rgb": format!("#{:02x}{:02x}{:02x}", r, g, b),
+                    "back_hash": hex::encode(pixel.back_hash),
+                })
+            });
+            json!({
+                "message": hex::encode(&message[..]),
+                "pixel": pixel,
+            })
+        });
+    let inputs: Vec<Value> = transaction
+        .get_inputs()
+        .iter()
+        .map(|input| {
+            json!({
+                "block_hash": hex::encode(input.block_hash.hash()),
+                "transaction_hash": hex::encode(input.transaction_hash.hash()),
+                "output_index": input.output_index.get_value(),
+            })
+        })
+        .collect();
+    let outputs: Vec<Value> = transaction
+        .get_outputs()
+        .iter()
+        .map(|output| {
+            let pk = hex::encode(&output.pk.serialize()[..]);
+            match output.value.get_value() {
+                Ok(value) => json!({
+                    "kind": "value",
+                    "value_cel": format_cel(value),
+                    "pk": pk,
+                }),
+                Err(_) => json!({
+                    "kind": "id_transfer",
+                    "id": output.value.get_id().map(hex::encode).ok(),
+                    "pk": pk,
+                }),
+            }
+        })
+        .collect();
+    json!({
+        "hash": hex::encode(transaction.hash().hash()),
+        "base": base,
+        "inputs": inputs,
+        "outputs": outputs,
+    })
+}
+
+fn describe_block(index: usize, entry: &BlockEntry, wallet: Option<&Wallet>) -> Value {
+    let block = &entry.block;
+    let transactions = wallet.map(|wallet| {
+        wallet
+            .on_chain_transactions
+            .get(&block.hash())
+            .map(|transactions| {
+                transactions
+                    .values()
+                    .map(describe_transaction)
+                    .collect::<Vec<_>>()
+            })
+    });
+    json!({
+        "block": index,
+        "offset": entry.offset,
+        "bytes": entry.end - entry.offset,
+        "hash": hex::encode(block.hash().hash()),
+        "back_hash": hex::encode(block.back_hash.hash()),
+        "transactions_hash": hex::encode(block.transactions_hash().hash()),
+        "mined": BlockHash::contains_enough_work(&block.hash().hash()),
+        "transactions": transactions,
+    })
+}
+
+/// Renders `value` as an indented tree, one leaf per line.
+fn tree(title: &str, value: &Value) -> String {
+    let mut lines = vec![title.to_string()];
+    push_tree(&mut lines, "", value);
+    lines.join("\n")
+}
+
+fn push_tree(lines: &mut Vec<String>, prefix: &str, value: &Value) {
+    let children: Vec<(String, &Value)> = match value {
+        Value::Object(fields) => fields.iter().map(|(k, v)| (k.clone(), v)).collect(),
+        Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i.to_string(), v))
+            .collect(),
+        _ => return,
+    };
+    for (i, (key, child)) in children.iter().enumerate() {
+        let (branch, indent) = if i + 1 == children.len() {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        match child {
+            Value::Object(fields) if !fields.is_empty() => {
+                lines.push(format!("{}{}{}", prefix, branch, key));
+                push_tree(lines, &format!("{}{}", prefix, indent), child);
+            }
+            Value::Array(items) if !items.is_empty() => {
+                lines.push(format!("{}{}{}", prefix, branch, key));
+                push_tree(lines, &format!("{}{}", prefix, indent), child);
+            }
+            Value::Object(_) | Value::Array(_) => {
+                lines.push(format!("{}{}{}: none", prefix, branch, key))
+            }
+            Value::Null => lines.push(format!("{}{}{}: -", prefix, branch, key)),
+            Value::String(s) => lines.push(format!("{}{}{}: {}", prefix, branch, key, s)),
+            other => lines.push(format!("{}{}{}: {}", prefix, branch, key, other)),
+        }
+    }
+}
+
+fn emit_transactions<'a>(
+    transactions: impl Iterator<Item = &'a Transaction>,
+    selector: Option<&Selector>,
+    output: &mut Output,
+) -> usize {
+    let mut shown = 0;
+    for (i, transaction) in transactions.enumerate() {
+        let index = i + 1;
+        if selector.map_or(true, |s| s.matches(index, transaction.hash().hash())) {
+            let mut value = describe_transaction(transaction);
+            value["transaction"] = json!(index);
+            output.emit(
+                "transaction",
+                value.clone(),
+                tree(&format!("Transaction {}", index), &value),
+            );
+            shown += 1;
+        }
+    }
+    shown
+}
+
+pub fn run(options: &Options, output: &mut Output) -> Result<(), CliError> {
+    let selector = options.selector.as_ref();
+    let shown = match &options.source {
+        Source::Blocks { path, data } => {
+            let wallet = match data {
+                Some(data) => Some(DataDir::open(data)?.load()?),
+                None => None,
+            };
+            let mut shown = 0;
+            for (i, entry) in BlocksFile::new(path).blocks()?.enumerate() {
+                let entry = entry?;
+                let index = i + 1;
+                if selector.map_or(true, |s| s.matches(index, entry.block.hash().hash())) {
+                    let value = describe_block(index, &entry, wallet.as_ref());
+                    output.emit(
+                        "block",
+                        value.clone(),
+                        tree(&format!("Block {}", index), &value),
+                    );
+                    shown += 1;
+                }
+            }
+            shown
+        }
+        Source::Transactions { path } => {
+            let data = read_file(path)?;
+            let mut transactions = Vec::new();
+            let mut offset = 0;
+            while offset < data.len() {
+                let start = offset;
+                let transaction = Transaction::from_serialized(&data, &mut offset)
+                    .map_err(|e| CliError::serialization(path, Some(start), e))?;
+                if offset <= start || offset > data.len() {
+                    return Err(CliError::serialization(
+                        path,
+                        Some(start),
+                        format!("Transaction claims to end at byte {}", offset),
+                    ));
+                }
+                transactions.push(*transaction);
+            }
+            emit_transactions(transactions.iter(), selector, output)
+        }
+        Source::OffChain { data } => {
+            let data_dir = DataDir::open(data)?;
+            let wallet =
+                data_dir.wallet_from_binary(&data_dir.load_binary()?, Default::default())?;
+            emit_transactions(wallet.off_chain_transactions.values(), selector, output)
+        }
+    };
+    if shown == 0 && selector.is_some() {
+        return Err(CliError::Usage("Nothing matches the selector".to_string()));
+    }
+    Ok(())
+}
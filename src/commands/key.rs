@@ -0,0 +1,212 @@
+use crate::{
+    error::CliError,
+    key_file::{self, KeyFormat, Passphrase},
+    output::Output,
+    read_file, write_private_atomically,
+};
+use colored::*;
+use secp256k1::{rand::thread_rng, Secp256k1};
+use serde_json::json;
+use std::{
+    io::{self, Read, Write},
+    path::Path,
+};
+
+pub enum Options {
+    /// Generate a key and write it in the canonical format
+    New {
+        /// Path to write the secret key file to
+        path: String,
+        /// Encrypt the key with a passphrase read from here
+        encrypt: Option<Passphrase>,
+    },
+    /// Print the public key of a secret key file
+    Show {
+        /// Path to secret key file
+        path: String,
+        passphrase: Passphrase,
+    },
+    /// Read a key in any format and write it in the canonical format
+    Import {
+        /// Path to read the key from, stdin if "-"
+        input: String,
+        /// Path to write the secret key file to
+        path: String,
+        /// Passphrase of the key read, if it is encrypted
+        passphrase: Passphrase,
+        /// Encrypt the key with a passphrase read from here
+        encrypt: Option<Passphrase>,
+    },
+    /// Write a secret key file in another format
+    Export {
+        /// Path to secret key file
+        path: String,
+        passphrase: Passphrase,
+        format: KeyFormat,
+        /// Path to write the key to, stdout if `None`
+        output: Option<String>,
+    },
+    /// Change the passphrase of a secret key file, or encrypt or decrypt it
+    Rekey {
+        /// Path to secret key file
+        path: String,
+        /// Current passphrase, if the key is encrypted
+        passphrase: Passphrase,
+        /// New passphrase, the key is stored unencrypted if `None`
+        new_passphrase: Option<Passphrase>,
+    },
+}
+
+pub fn run(options: &Options, output: &mut Output) -> Result<(), CliError> {
+    match options {
+        Options::New { path, encrypt } => new(path, encrypt.as_ref(), output),
+        Options::Show { path, passphrase } => show(path, passphrase, output),
+        Options::Import {
+            input,
+            path,
+            passphrase,
+            encrypt,
+        } => import(input, path, passphrase, encrypt.as_ref(), output),
+        Options::Export {
+            path,
+            passphrase,
+            format,
+            output: export_path,
+        } => export(path, passphrase, *format, export_path.as_deref(), output),
+        Options::Rekey {
+            path,
+            passphrase,
+            new_passphrase,
+        } => rekey(path, passphrase, new_passphrase.as_ref(), output),
+    }
+}
+
+fn refuse_overwrite(path: &str) -> Result<(), CliError> {
+    if Path::new(path).exists() {
+        return Err(CliError::Usage(format!(
+            "\"{}\" already exists, not overwriting it",
+            path
+        )));
+    }
+    Ok(())
+}
+
+fn new(path: &str, encrypt: Option<&Passphrase>, output: &mut Output) -> Result<(), CliError> {
+    refuse_overwrite(path)?;
+    let new_passphrase = encrypt.map(Passphrase::read_new).transpose()?;
+    let (sk, _) = Secp256k1::new().generate_keypair(&mut thread_rng());
+    key_file::write(path, &sk, new_passphrase.as_deref())?;
+    let pk = key_file::pk_hex(&sk);
+    output.emit(
+        "created",
+        json!({ "path": path, "pk": pk }),
+        format!("Wrote new key to {:?}\n{}", path, pk),
+    );
+    Ok(())
+}
+
+fn show(path: &str, passphrase: &Passphrase, output: &mut Output) -> Result<(), CliError> {
+    let (sk, format) = key_file::read(path, passphrase)?;
+    let pk = key_file::pk_hex(&sk);
+    let legacy = format != KeyFormat::Binary && format != KeyFormat::Encrypted;
+    output.emit(
+        "key",
+        json!({ "path": path, "pk": pk, "format": format.to_string(), "legacy": legacy }),
+        pk,
+    );
+    if legacy {
+        output.info(
+            format!(
+                "{:?} is stored as {}, run key import to convert it to the canonical binary format",
+                path, format
+            )
+            .yellow()
+            .to_string(),
+        );
+    }
+    Ok(())
+}
+
+fn import(
+    input: &str,
+    path: &str,
+    passphrase: &Passphrase,
+    encrypt: Option<&Passphrase>,
+    output: &mut Output,
+) -> Result<(), CliError> {
+    // Converting a legacy file in place is allowed, anything else is not
+    // overwritten
+    if input != path {
+        refuse_overwrite(path)?;
+    }
+    let data = if input == "-" {
+        let mut data = Vec::new();
+        io::stdin()
+            .read_to_end(&mut data)
+            .map_err(|e| CliError::io("<stdin>", e))?;
+        data
+    } else {
+        read_file(input)?
+    };
+    let (sk, format) = key_file::unlock(input, &data, passphrase)?;
+    let new_passphrase = encrypt.map(Passphrase::read_new).transpose()?;
+    key_file::write(path, &sk, new_passphrase.as_deref())?;
+    let pk = key_file::pk_hex(&sk);
+    output.emit(
+        "imported",
+        json!({ "path": path, "pk": pk, "format": format.to_string() }),
+        format!("Imported {} key to {:?}\n{}", format, path, pk),
+    );
+    Ok(())
+}
+
+fn export(
+    path: &str,
+    passphrase: &Passphrase,
+    format: KeyFormat,
+    export_path: Option<&str>,
+    output: &mut Output,
+) -> Result<(), CliError> {
+    let (sk, _) = key_file::read(path, passphrase)?;
+    let data = key_file::encode(&sk, format).map_err(|e| CliError::serialization(path, None, e))?;
+    match export_path {
+        Some(export_path) => {
+            refuse_overwrite(export_path)?;
+            write_private_atomically(export_path, &data)
+                .map_err(|e| CliError::io(export_path, e))?;
+            output.emit(
+                "exported",
+                json!({ "path": export_path, "format": format.to_string() }),
+                format!("Exported key as {} to {:?}", format, export_path),
+            );
+        }
+        None => io::stdout()
+            .write_all(&data)
+            .map_err(|e| CliError::io("<stdout>", e))?,
+    }
+    Ok(())
+}
+
+fn rekey(
+    path: &str,
+    passphrase: &Passphrase,
+    new_passphrase: Option<&Passphrase>,
+    output: &mut Output,
+) -> Result<(), CliError> {
+    let (sk, format) = key_file::read(path, passphrase)?;
+    let new_passphrase = new_passphrase.map(Passphrase::read_new).transpose()?;
+    key_file::write(path, &sk, new_passphrase.as_deref())?;
+    let encrypted = new_passphrase.is_some();
+    output.emit(
+        "rekeyed",
+        json!({ "path": path, "from": format.to_string(), "encrypted": encrypted }),
+        if encrypted {
+            format!("Encrypted {:?} with the new passphrase", path)
+        } else {
+            format!("Stored {:?} unencrypted", path)
+        }
+        .green()
+        .to_string(),
+    );
+    Ok(())
+}
@@ -0,0 +1,243 @@
+use crate::{
+    blocks_file::{BlocksFile, BlocksWriter},
+    error::CliError,
+    key_file::{self, Passphrase},
+    output::Output,
+    read_file, write_atomically,
+};
+use celestium::{block_hash::BlockHash, serialize::Serialize, wallet::Wallet};
+use colored::*;
+use secp256k1::{PublicKey, Secp256k1};
+use serde::{Deserialize, Serialize as SerdeSerialize};
+use serde_json::json;
+use std::{
+    fs::remove_file,
+    io::{self, Write},
+    path::Path,
+    time::Instant,
+};
+
+pub struct Options {
+    /// Path to binary blocks file
+    pub blocks: String,
+    /// Path to binary Secret Key file
+    pub sk: String,
+    /// Continue from the last checkpoint recorded in the journal
+    pub resume: bool,
+    /// Discard the checkpoint of an interrupted run instead of refusing to
+    /// start
+    pub restart: bool,
+    /// Re-link and re-mine every block from the first broken link onward
+    pub rechain: bool,
+    /// Number of threads to mine with
+    pub threads: u64,
+    /// Amount of work handed to each thread at a time
+    pub par_work: u64,
+    /// Where to get the passphrase of an encrypted secret key from
+    pub passphrase: Passphrase,
+}
+
+/// Sidecar for a blocks file being mined, recording the last block whose
+/// checkpoint was committed to disk.
+///
+/// Mined blocks are written to the `.partial` sibling of the blocks file,
+/// which replaces it once every block is mined.
+#[derive(SerdeSerialize, Deserialize, Debug)]
+pub struct MineJournal {
+    pub committed_blocks: usize,
+    /// Length of the `.partial` file up to and including the committed block
+    pub committed_bytes: usize,
+    /// Offset in the blocks file of the block following the committed one
+    pub input_bytes: usize,
+    pub back_hash: String,
+}
+
+impl MineJournal {
+    pub fn path(blocks_path: &str) -> String {
+        format!("{}.journal", blocks_path)
+    }
+
+    pub fn load(path: &str) -> Result<MineJournal, CliError> {
+        serde_json::from_slice(&read_file(path)?)
+            .map_err(|e| CliError::serialization(path, None, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), CliError> {
+        let data = serde_json::to_vec(self).map_err(|e| CliError::serialization(path, None, e))?;
+        write_atomically(path, &data).map_err(|e| CliError::io(path, e))
+    }
+
+    pub fn back_hash(&self) -> Result<BlockHash, CliError> {
+        let back_hash = hex::decode(&self.back_hash)
+            .map_err(|e| CliError::serialization("journal back_hash", None, e))?;
+        Ok(*BlockHash::from_serialized(&back_hash, &mut 0)
+            .map_err(|e| CliError::serialization("journal back_hash", None, e))?)
+    }
+}
+
+pub fn run(options: &Options, output: &mut Output) -> Result<(), CliError> {
+    let serialized_blocks_location = &options.blocks;
+    let serialized_sk_location = &options.sk;
+    let journal_location = MineJournal::path(serialized_blocks_location);
+
+    let blocks_file = BlocksFile::new(serialized_blocks_location);
+
+    let secp = Secp256k1::new();
+    let (sk, _) = key_file::read(serialized_sk_location, &options.passphrase)?;
+    let pk = PublicKey::from_secret_key(&secp, &sk);
+    let wallet = Wallet::new(pk, sk).map_err(CliError::Wallet)?;
+
+    let journal = if options.resume {
+        let journal = MineJournal::load(&journal_location)?;
+        output.emit(
+            "resume",
+            json!({
+                "committed_blocks": journal.committed_blocks,
+                "committed_bytes": journal.committed_bytes,
+                "input_bytes": journal.input_bytes,
+                "back_hash": journal.back_hash,
+            }),
+            format!(
+                "Resuming after block {} (byte {})",
+                journal.committed_blocks, journal.input_bytes
+            ),
+        );
+        Some(journal)
+    } else {
+        let partial_location = BlocksWriter::partial_path(serialized_blocks_location);
+        let journal_exists = Path::new(&journal_location).exists();
+        if !options.restart && (journal_exists || Path::new(&partial_location).exists()) {
+            return Err(CliError::Usage(format!(
+                "An interrupted run left its mined blocks in {:?}, continue it with --resume or discard them with --restart",
+                partial_location
+            )));
+        }
+        if journal_exists {
+            remove_file(&journal_location).map_err(|e| CliError::io(&journal_location, e))?;
+        }
+        None
+    };
+    let (committed_blocks, input_bytes) = journal
+        .as_ref()
+        .map_or((0, 0), |j| (j.committed_blocks, j.input_bytes));
+
+    let mut blocks = blocks_file.blocks_from(input_bytes)?;
+    let mut blocks_writer = match &journal {
+        Some(journal) => BlocksWriter::resume(serialized_blocks_location, journal.committed_bytes)?,
+        None => BlocksWriter::create(serialized_blocks_location)?,
+    };
+    // Hash of the last block in the chain so far, `None` before the genesis block
+    let mut back_hash = match &journal {
+        Some(journal) => Some(journal.back_hash()?),
+        None => None,
+    };
+    output.emit(
+        "start",
+        json!({ "committed_blocks": committed_blocks, "input_bytes": input_bytes }),
+        format!("Starting mining at byte {}", input_bytes),
+    );
+    for (n, entry) in blocks.by_ref().enumerate() {
+        let entry = entry?;
+        let mut block = entry.block;
+        let block_n = committed_blocks + n + 1;
+        let linked = back_hash
+            .as_ref()
+            .map_or(true, |h| block.back_hash.hash() == h.hash());
+        if BlockHash::contains_enough_work(&block.hash().hash()) {
+            if linked {
+                output.emit(
+                    "already_mined",
+                    json!({
+                        "block": block_n,
+                        "hash": hex::encode(block.hash().hash()),
+                    }),
+                    format!("Block {} already mined ✔️", block_n)
+                        .green()
+                        .to_string(),
+                );
+                back_hash = Some(block.hash());
+                blocks_writer.write_block(&block)?;
+                continue;
+            }
+            let expected = back_hash.as_ref().unwrap();
+            output.emit(
+                "broken_link",
+                json!({
+                    "block": block_n,
+                    "back_hash": hex::encode(block.back_hash.hash()),
+                    "expected_back_hash": hex::encode(expected.hash()),
+                    "rechain": options.rechain,
+                }),
+                format!(
+                    "Block {} is mined but links to {}, expected {}",
+                    block_n,
+                    hex::encode(block.back_hash.hash()),
+                    hex::encode(expected.hash())
+                )
+                .yellow()
+                .to_string(),
+            );
+            if !options.rechain {
+                return Err(CliError::Verification(format!(
+                    "Broken link at block {}, run with --rechain to re-link and re-mine from this block onward",
+                    block_n
+                )));
+            }
+        }
+        if output.is_text() {
+            print!("Mining block {}", block_n);
+            io::stdout()
+                .flush()
+                .map_err(|e| CliError::io("<stdout>", e))?;
+        }
+        if let Some(h) = back_hash.take() {
+            block.back_hash = h;
+        }
+        let start = Instant::now();
+        let mined_block = wallet
+            .mine_block(options.threads, options.par_work, block)
+            .map_err(|e| CliError::Wallet(format!("Could not mine block {}. {}", block_n, e)))?;
+        let elapsed = start.elapsed();
+        output.emit(
+            "mined",
+            json!({
+                "block": block_n,
+                "hash": hex::encode(mined_block.hash().hash()),
+                "elapsed_ms": elapsed.as_millis() as u64,
+            }),
+            format!("{}\nTime: {:?}", ". Done ✔️".green(), elapsed),
+        );
+        back_hash = Some(mined_block.hash());
+        blocks_writer.write_block(&mined_block)?;
+        output.emit(
+            "checkpoint",
+            json!({
+                "block": block_n,
+                "bytes": blocks_writer.len(),
+                "path": BlocksWriter::partial_path(serialized_blocks_location),
+            }),
+            format!(
+                "Saving checkpoint ({}B) to {:?}",
+                blocks_writer.len(),
+                BlocksWriter::partial_path(serialized_blocks_location)
+            ),
+        );
+        blocks_writer.sync()?;
+        let journal = MineJournal {
+            committed_blocks: block_n,
+            committed_bytes: blocks_writer.len(),
+            input_bytes: entry.end,
+            back_hash: hex::encode(mined_block.hash().hash()),
+        };
+        journal.save(&journal_location)?;
+    }
+    if let Some(end_magic) = blocks.end_magic() {
+        output.info(format!("Got blocks end at byte {}", end_magic));
+    }
+    blocks.copy_rest(&mut blocks_writer)?;
+    blocks_writer.commit()?;
+    if Path::new(&journal_location).exists() {
+        remove_file(&journal_location).map_err(|e| CliError::io(&journal_location, e))?;
+    }
+    Ok(())
+}
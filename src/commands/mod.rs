@@ -0,0 +1,18 @@
+//! One module per subcommand, each running from its own `Options`.
+
+pub mod backup;
+pub mod balance;
+pub mod bench;
+pub mod collect;
+pub mod config;
+pub mod count;
+pub mod generate;
+pub mod inspect;
+pub mod key;
+pub mod mine;
+pub mod paint;
+pub mod piximg;
+pub mod random;
+pub mod send;
+pub mod utxo;
+pub mod verify;
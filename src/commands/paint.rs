@@ -0,0 +1,175 @@
+use crate::{error::CliError, output::Output, pixel::quantize, pixel_client::PixelClient};
+use celestium::{
+    transaction::Transaction, transaction_output::TransactionOutput,
+    transaction_value::TransactionValue, wallet::Wallet,
+};
+use rand::{seq::SliceRandom, thread_rng};
+use rayon::ThreadPoolBuilder;
+use secp256k1::PublicKey;
+use serde::Deserialize;
+use serde_json::json;
+use sha3::{Digest, Sha3_256};
+use std::{fs, io, time::Instant};
+
+#[derive(Deserialize, Debug)]
+struct Data {
+    data: Vec<[u16; 3]>,
+}
+
+/// What to paint.
+pub enum Pixels {
+    /// JSON file holding `{"data": [[x, y, color], ...]}`
+    File(String),
+    /// Image quantized to the palette, with its top left corner at `at`
+    Image {
+        path: String,
+        at: (u16, u16),
+        dither: bool,
+    },
+}
+
+pub struct Options {
+    /// Websocket URL of the Celestium server
+    pub server_url: String,
+    /// Key to mine pixels for
+    pub pk: PublicKey,
+    pub pixels: Pixels,
+    /// Number of threads to mine with
+    pub threads: u64,
+    /// Amount of work handed to each thread at a time
+    pub par_work: u64,
+}
+
+pub fn run(options: &Options, output: &mut Output) -> Result<(), CliError> {
+    let mut client = PixelClient::connect(&options.server_url)?;
+    let thread_pool = ThreadPoolBuilder::new()
+        .num_threads(options.threads as usize)
+        .build()
+        .map_err(|e| CliError::System(format!("Could not start threads. {}", e)))?;
+    let pk = options.pk;
+
+    let mut pixels = load_pixels(&options.pixels, output)?;
+    pixels.shuffle(&mut thread_rng());
+
+    let pb = output.progress_bar(pixels.len() as u64, "Mining...");
+    for [x, y, c] in pixels {
+        let start = Instant::now();
+        let current_color = client.get_pixel_color(x, y);
+        report_server_errors(&mut client, output, x, y);
+        if current_color? == c as u8 {
+            output.emit(
+                "pixel_skipped",
+                json!({ "x": x, "y": y, "color": c }),
+                format!("({}, {}) already set to {}, skipping", x, y, c),
+            );
+            continue;
+        }
+
+        let mining_data = client.request_mining_data(x, y, &pk);
+        report_server_errors(&mut client, output, x, y);
+        let mining_data = mining_data?;
+        let mut message = [0x0; 33];
+        message[0..28].copy_from_slice(&mining_data.pixel_back_hash);
+        message[28..30].copy_from_slice(&x.to_be_bytes());
+        message[30..32].copy_from_slice(&y.to_be_bytes());
+        message[32] = c as u8;
+
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&Sha3_256::digest(&message));
+        let pixel_transaction = Transaction::new_id_base_transaction(
+            mining_data.block_head_hash,
+            message,
+            TransactionOutput::new(
+                TransactionValue::new_id_transfer(hash).map_err(CliError::Wallet)?,
+                pk,
+            ),
+        )
+        .map_err(CliError::Wallet)?;
+
+        let pixel_transaction = Wallet::mine_transaction(
+            options.threads,
+            options.par_work,
+            pixel_transaction,
+            &thread_pool,
+        )
+        .map_err(CliError::Wallet)?;
+
+        let katjing_transaction = Wallet::mine_transaction(
+            options.threads,
+            options.par_work,
+            mining_data.katjing_transaction,
+            &thread_pool,
+        )
+        .map_err(CliError::Wallet)?;
+
+        client.submit_transactions(&[pixel_transaction, katjing_transaction])?;
+        output.record(
+            "pixel_submitted",
+            json!({
+                "x": x,
+                "y": y,
+                "color": c,
+                "elapsed_ms": start.elapsed().as_millis() as u64,
+            }),
+        );
+
+        pb.inc(1);
+    }
+    pb.finish();
+    Ok(())
+}
+
+/// Reads or generates the `[x, y, color]` pixels to place.
+fn load_pixels(pixels: &Pixels, output: &mut Output) -> Result<Vec<[u16; 3]>, CliError> {
+    match pixels {
+        Pixels::File(path) => {
+            let data = fs::read_to_string(path).map_err(|e| CliError::io(path, e))?;
+            let json: Data =
+                serde_json::from_str(&data).map_err(|e| CliError::serialization(path, None, e))?;
+            Ok(json.data)
+        }
+        Pixels::Image { path, at, dither } => {
+            let image = image::open(path)
+                .map_err(|e| match e {
+                    image::ImageError::IoError(e) => CliError::io(path, e),
+                    e => CliError::io(path, io::Error::new(io::ErrorKind::InvalidData, e)),
+                })?
+                .to_rgba8();
+            let quantized = quantize(&image, *dither);
+            let opaque = quantized.len();
+            let pixels: Vec<[u16; 3]> = quantized
+                .into_iter()
+                .map(|(x, y, color)| (at.0 as u32 + x, at.1 as u32 + y, color))
+                .filter(|&(x, y, _)| x < 1000 && y < 1000)
+                .map(|(x, y, color)| [x as u16, y as u16, color as u16])
+                .collect();
+            output.emit(
+                "quantized",
+                json!({
+                    "width": image.width(),
+                    "height": image.height(),
+                    "pixels": pixels.len(),
+                    "off_canvas": opaque - pixels.len(),
+                }),
+                format!(
+                    "Quantized {}x{} image to {} pixels, {} off the canvas",
+                    image.width(),
+                    image.height(),
+                    pixels.len(),
+                    opaque - pixels.len()
+                ),
+            );
+            Ok(pixels)
+        }
+    }
+}
+
+fn report_server_errors(client: &mut PixelClient, output: &mut Output, x: u16, y: u16) {
+    for error in client.server_errors.drain(..) {
+        output.emit(
+            "server_error",
+            json!({ "x": x, "y": y, "error": error }),
+            format!("ERR: {}", error),
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{
+    error::CliError,
+    output::Output,
+    pixel::{Pixel, COLOR_MAP},
+    read_file,
+};
+use celestium::transaction::Transaction;
+use image::{ImageFormat, Rgba, RgbaImage};
+use serde_json::json;
+use std::io;
+
+pub struct Options {
+    /// Path to off chain transactions file
+    pub file: String,
+    /// Path to save frames of video
+    pub directory: String,
+}
+
+pub fn run(options: &Options, output: &mut Output) -> Result<(), CliError> {
+    let off_chain_transactions_file = &options.file;
+    let output_dir = &options.directory;
+    let off_chain_transactions_bin = read_file(off_chain_transactions_file)?;
+    let mut i = 0;
+    let mut ii = 0;
+    let mut image = RgbaImage::new(1000, 1000);
+    for y in 0..1000 {
+        for x in 0..1000 {
+            image.put_pixel(x, y, Rgba([0xff, 0xff, 0xff, 0xff]));
+        }
+    }
+    let mut pixels = Vec::new();
+    while i < off_chain_transactions_bin.len() {
+        let start = i;
+        let t = *Transaction::from_serialized(&off_chain_transactions_bin, &mut i)
+            .map_err(|e| CliError::serialization(off_chain_transactions_file, Some(start), e))?;
+        if let Ok(bytes) = t.get_base_transaction_message() {
+            match Pixel::from_base_message(&bytes[..]) {
+                Some(pixel) => {
+                    pixels.push((pixel, format!("{}{:0>10}.png", output_dir, ii)));
+                    ii += 1;
+                }
+                None => output.emit(
+                    "skipped",
+                    json!({ "offset": start, "message": hex::encode(&bytes[..]) }),
+                    format!(
+                        "Skipping transaction at byte {}, its base message does not set a pixel",
+                        start
+                    ),
+                ),
+            }
+        }
+    }
+
+    let frames = pixels.len();
+    let pb = output.progress_bar(frames as u64, "Generating frames");
+    for (pixel, file_name) in pixels {
+        pb.inc(1);
+        let color = COLOR_MAP[pixel.color as usize];
+        image.put_pixel(pixel.x as u32, pixel.y as u32, Rgba(color));
+        image
+            .save_with_format(&file_name, ImageFormat::Png)
+            .map_err(|e| CliError::io(&file_name, io::Error::new(io::ErrorKind::Other, e)))?;
+    }
+    pb.finish();
+    output.emit(
+        "frames",
+        json!({ "frames": frames, "path": output_dir }),
+        format!("Generated {} frames in {}", frames, output_dir),
+    );
+    Ok(())
+    // ffmpeg -r 120 -i video/%10d.png -c:v libx265 canvas.mp4
+}
@@ -0,0 +1,198 @@
+use crate::{error::CliError, output::Output};
+use image::{io::Reader as ImageReader, GenericImageView, ImageFormat, Rgba, RgbaImage};
+use probability::{self, distribution::Sample};
+use serde_json::json;
+use std::{
+    cmp::{max, min},
+    fs, io,
+};
+
+pub struct Options {
+    /// Path to directory containing noisy images
+    pub images: String,
+    /// Path to location to save binary file containing random z-vectors
+    pub output: String,
+    /// Amount of z-vectors to generate
+    pub count: usize,
+    /// Size of z-vectors to generate
+    pub size: usize,
+}
+
+fn diff(r: (u8, u8)) -> u32 {
+    if r.0 > r.1 {
+        (r.0 - r.1) as u32
+    } else {
+        (r.1 - r.0) as u32
+    }
+}
+
+pub fn run(options: &Options, output: &mut Output) -> Result<(), CliError> {
+    let random_images_location = &options.images;
+    let dir = fs::read_dir(random_images_location)
+        .and_then(|dir| dir.collect::<io::Result<Vec<_>>>())
+        .map_err(|e| CliError::io(random_images_location, e))?;
+    output.info(format!("Found {} images", dir.len()));
+    let image_width = 1280usize; // TODO: Get from images
+    let image_height = 720usize; // TODO: Get from images
+    if options.count > image_width * image_height {
+        return Err(CliError::Usage(format!(
+            "--count is {}, but the images only have {} pixels to take z-vectors from",
+            options.count,
+            image_width * image_height
+        )));
+    }
+    let mut all_img_pixels: Vec<Vec<Vec<Rgba<u8>>>> =
+        vec![vec![Vec::new(); image_width]; image_height];
+    for entry in dir {
+        let entry = entry.path();
+        let entry_location = entry.to_string_lossy().to_string();
+        output.info(format!("Parsing image {:?}", entry));
+        let img = ImageReader::open(&entry)
+            .map_err(|e| CliError::io(&entry_location, e))?
+            .decode()
+            .map_err(|e| CliError::serialization(&entry_location, None, e))?;
+        let (width, height) = img.dimensions();
+        if (width as usize, height as usize) != (image_width, image_height) {
+            return Err(CliError::serialization(
+                &entry_location,
+                None,
+                format!(
+                    "Image is {}x{}, expected {}x{}",
+                    width, height, image_width, image_height
+                ),
+            ));
+        }
+        // One row per y, each holding one pixel stack per x
+        for (y, row) in all_img_pixels.iter_mut().enumerate() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                pixel.push(img.get_pixel(x as u32, y as u32));
+            }
+        }
+    }
+    let mut img = RgbaImage::new(image_width as u32, image_height as u32);
+    let mut diffs: Vec<(u32, u32, u32, u32, u16, u16)> =
+        vec![(0, 0, 0, 0, 0, 0); image_width * image_height];
+    for (y, inner) in all_img_pixels.iter().enumerate() {
+        for (x, pixels) in inner.iter().enumerate() {
+            let mut r_total_diff = 0;
+            let mut g_total_diff = 0;
+            let mut b_total_diff = 0;
+            let mut a_total_diff = 0;
+            for pixel1 in pixels {
+                for pixel2 in pixels {
+                    if pixel1 != pixel2 {
+                        r_total_diff += diff((pixel1.0[0], pixel2.0[0]));
+                        g_total_diff += diff((pixel1.0[1], pixel2.0[1]));
+                        b_total_diff += diff((pixel1.0[2], pixel2.0[2]));
+                        a_total_diff += diff((pixel1.0[3], pixel2.0[3]));
+                    }
+                }
+            }
+            diffs[x + y * image_width] = (
+                r_total_diff,
+                g_total_diff,
+                b_total_diff,
+                a_total_diff,
+                x as u16,
+                y as u16,
+            );
+        }
+    }
+
+    diffs.sort_by(|&(r0, g0, b0, a0, _, _), &(r1, g1, b1, a1, _, _)| {
+        ((r1 as usize) + (g1 as usize) + (b1 as usize) + (a1 as usize))
+            .cmp(&((r0 as usize) + (g0 as usize) + (b0 as usize) + (a0 as usize)))
+    });
+
+    let count = options.count;
+
+    let top_diffs = &diffs[..min(count, diffs.len())];
+
+    let mut max_r_diff = u32::MIN;
+    let mut min_r_diff = u32::MAX;
+    let mut max_g_diff = u32::MIN;
+    let mut min_g_diff = u32::MAX;
+    let mut max_b_diff = u32::MIN;
+    let mut min_b_diff = u32::MAX;
+    let mut max_a_diff = u32::MIN;
+    let mut min_a_diff = u32::MAX;
+
+    for (r, g, b, a, _, _) in top_diffs {
+        max_r_diff = max(max_r_diff, *r);
+        min_r_diff = min(min_r_diff, *r);
+        max_g_diff = max(max_g_diff, *g);
+        min_g_diff = min(min_g_diff, *g);
+        max_b_diff = max(max_b_diff, *b);
+        min_b_diff = min(min_b_diff, *b);
+        max_a_diff = max(max_a_diff, *a);
+        min_a_diff = min(min_a_diff, *a);
+    }
+
+    let mut normalized_diffs: Vec<(u8, u8, u8, u8, u16, u16)> =
+        vec![(0, 0, 0, 0, 0, 0); top_diffs.len()];
+    for (i, (r, g, b, _, x, y)) in top_diffs.iter().enumerate() {
+        let r = (((*r - min_r_diff) as f64 / (max_r_diff as f64)) * 255.0) as u8;
+        let g = (((*g - min_g_diff) as f64 / (max_g_diff as f64)) * 255.0) as u8;
+        let b = (((*b - min_b_diff) as f64 / (max_b_diff as f64)) * 255.0) as u8;
+        let a = 255u8;
+        img.put_pixel(*x as u32, *y as u32, Rgba([r, g, b, a]));
+        normalized_diffs[i] = (r, g, b, a, *x, *y);
+    }
+    img.save_with_format("output.png", ImageFormat::Png)
+        .map_err(|e| CliError::io("output.png", io::Error::new(io::ErrorKind::Other, e)))?;
+
+    let size = options.size;
+    output.info(format!("Generating {} z-vectors", count));
+    let distribution = probability::distribution::Gaussian::new(0.0, 1.0);
+    let z_vectors: Vec<Vec<_>> = normalized_diffs
+        .iter()
+        .map(|diff| {
+            let mut seed = probability::source::Default::new().seed([
+                ((diff.0 as u64) << 24)
+                    + ((diff.1 as u64) << 16)
+                    + ((diff.2 as u64) << 8)
+                    + (diff.3 as u64),
+                ((diff.4 as u64) << 16) + (diff.5 as u64),
+            ]);
+            (0..size)
+                .map(move |_| distribution.sample(&mut seed))
+                .collect()
+        })
+        .collect();
+
+    let mut bin = vec![0u8; count * size * 8 + 4 * count];
+    output.info(format!("Total len {}", bin.len()));
+    let mut i = 0;
+    for z_vector in z_vectors {
+        for sample in z_vector {
+            bin[i..i + 8].copy_from_slice(&sample.to_be_bytes());
+            i += 8;
+        }
+    }
+    output.info(format!("Z-Vector cut off, coords from here on: {}", i));
+    for (x, y) in normalized_diffs
+        .iter()
+        .map(|diff| (diff.4, diff.5))
+        .collect::<Vec<(u16, u16)>>()
+    {
+        bin[i] = (x >> 8) as u8;
+        bin[i + 1] = (x & 0xff) as u8;
+        bin[i + 2] = (y >> 8) as u8;
+        bin[i + 3] = (y & 0xff) as u8;
+        i += 4;
+    }
+    let output_path = &options.output;
+    fs::write(output_path, &bin).map_err(|e| CliError::io(output_path, e))?;
+    output.emit(
+        "saved",
+        json!({
+            "z_vectors": count,
+            "size": size,
+            "coords_offset": count * size * 8,
+            "bytes": bin.len(),
+            "path": output_path,
+        }),
+        format!("Saved {} z-vectors to '{}'", count, output_path),
+    );
+    Ok(())
+}
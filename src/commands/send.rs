@@ -0,0 +1,178 @@
+use crate::{
+    commands::inspect::describe_transaction,
+    data_dir::{DataDir, LoadOptions},
+    error::CliError,
+    format_cel,
+    key_file::{self, Passphrase},
+    output::Output,
+    write_atomically,
+};
+use celestium::{
+    serialize::Serialize, transaction::Transaction, transaction_input::TransactionInput,
+    transaction_output::TransactionOutput, transaction_value::TransactionValue, wallet::Wallet,
+};
+use colored::*;
+use rayon::ThreadPoolBuilder;
+use secp256k1::{PublicKey, Secp256k1};
+use serde_json::json;
+use std::collections::HashSet;
+
+pub struct Options {
+    /// Path to data dir holding the sender's unspent outputs
+    pub data: String,
+    /// Path to secret key file of the sender, the data dir's own if `None`
+    pub sk: Option<String>,
+    /// Where to get the passphrase of an encrypted secret key from
+    pub passphrase: Passphrase,
+    /// Key to send to
+    pub to: PublicKey,
+    /// Amount to send in dust
+    pub amount: u128,
+    /// Path to write the mined transaction to instead of adding it to the
+    /// off chain transactions of the data dir
+    pub output: Option<String>,
+    /// Number of threads to mine with
+    pub threads: u64,
+    /// Amount of work handed to each thread at a time
+    pub par_work: u64,
+}
+
+pub fn run(options: &Options, output: &mut Output) -> Result<(), CliError> {
+    if options.amount == 0 {
+        return Err(CliError::Usage("--amount must be above 0".to_string()));
+    }
+    let mut data_dir = DataDir::open(&options.data)?;
+    data_dir.set_passphrase(options.passphrase.clone());
+
+    output.info("Loading wallet...");
+    // The wallet's own key is only unlocked if it is the one sending
+    let mut wallet = data_dir.wallet_from_binary(
+        &data_dir.load_binary()?,
+        LoadOptions {
+            need_sk: options.sk.is_none(),
+            ..LoadOptions::default()
+        },
+    )?;
+    output.info("Wallet loaded!");
+    let sk = match &options.sk {
+        Some(sk_path) => key_file::read(sk_path, &options.passphrase)?.0,
+        None => wallet.get_sk().map_err(CliError::Wallet)?,
+    };
+    let pk = PublicKey::from_secret_key(&Secp256k1::new(), &sk);
+
+    // Outputs already spent by a transaction waiting off chain
+    let mut pending = HashSet::new();
+    for (_, transaction) in &wallet.off_chain_transactions {
+        for input in transaction.get_inputs() {
+            pending.insert((
+                input.block_hash.hash(),
+                input.transaction_hash.hash(),
+                input.output_index.get_value(),
+            ));
+        }
+    }
+    let mut candidates = Vec::new();
+    if let Some(outputs) = wallet.unspent_outputs.get(&pk) {
+        for ((block_hash, transaction_hash, index), transaction_output) in outputs {
+            if pending.contains(&(
+                block_hash.hash(),
+                transaction_hash.hash(),
+                index.get_value(),
+            )) {
+                continue;
+            }
+            if let Ok(value) = transaction_output.value.get_value() {
+                candidates.push((value, block_hash, transaction_hash, index));
+            }
+        }
+    }
+    // Largest first, spending as few outputs as possible
+    candidates.sort_by(|a, b| b.0.cmp(&a.0));
+    let mut inputs = Vec::new();
+    let mut total = 0;
+    for (value, block_hash, transaction_hash, index) in candidates {
+        if total >= options.amount {
+            break;
+        }
+        total += value;
+        inputs.push(TransactionInput::new(
+            block_hash.clone(),
+            transaction_hash.clone(),
+            index.clone(),
+        ));
+    }
+    if total < options.amount {
+        return Err(CliError::Wallet(format!(
+            "Not enough funds, {} CEL available to send {} CEL",
+            format_cel(total),
+            format_cel(options.amount)
+        )));
+    }
+
+    let mut outputs = vec![TransactionOutput::new(
+        TransactionValue::new_coin_transfer(options.amount, 0).map_err(CliError::Wallet)?,
+        options.to,
+    )];
+    let change = total - options.amount;
+    if change > 0 {
+        outputs.push(TransactionOutput::new(
+            TransactionValue::new_coin_transfer(change, 0).map_err(CliError::Wallet)?,
+            pk,
+        ));
+    }
+    let n_inputs = inputs.len();
+    let mut transaction = Transaction::new(inputs, outputs).map_err(CliError::Wallet)?;
+    for i in 0..n_inputs {
+        transaction.sign(sk, i).map_err(CliError::Wallet)?;
+    }
+
+    output.info(format!(
+        "Mining transaction spending {} outputs...",
+        n_inputs
+    ));
+    let thread_pool = ThreadPoolBuilder::new()
+        .num_threads(options.threads as usize)
+        .build()
+        .map_err(|e| CliError::System(format!("Could not start threads. {}", e)))?;
+    let transaction =
+        Wallet::mine_transaction(options.threads, options.par_work, transaction, &thread_pool)
+            .map_err(CliError::Wallet)?;
+
+    let record = json!({
+        "amount_cel": format_cel(options.amount),
+        "change_cel": format_cel(change),
+        "transaction": describe_transaction(&transaction),
+    });
+    match &options.output {
+        Some(path) => {
+            let mut serialized = vec![0u8; transaction.serialized_len()];
+            transaction
+                .serialize_into(&mut serialized, &mut 0)
+                .map_err(|e| CliError::serialization(path, None, e))?;
+            write_atomically(path, &serialized).map_err(|e| CliError::io(path, e))?;
+        }
+        None => {
+            wallet
+                .add_off_chain_transaction(&transaction)
+                .map_err(CliError::Wallet)?;
+            data_dir.save(&wallet)?;
+        }
+    }
+    output.emit(
+        "sent",
+        record,
+        format!(
+            "Sent {} CEL to {}, {} CEL change, {}",
+            format_cel(options.amount),
+            hex::encode(&options.to.serialize()[..]),
+            format_cel(change),
+            match &options.output {
+                Some(path) => format!("written to {:?}", path),
+                None => format!("added to the off chain transactions of {}", data_dir.path()),
+            }
+        )
+        .green()
+        .to_string(),
+    );
+    Ok(())
+}
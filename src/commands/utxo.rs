@@ -0,0 +1,83 @@
+use crate::{data_dir::DataDir, error::CliError, format_cel, output::Output};
+use secp256k1::PublicKey;
+use serde_json::json;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ValueKind {
+    /// Outputs holding an amount of dust
+    Value,
+    /// Outputs transferring an NFT
+    IdTransfer,
+}
+
+pub enum Options {
+    /// List the unspent outputs of a data dir
+    List {
+        /// Path to data dir
+        data: String,
+        /// Only list outputs owned by these keys, all if empty
+        pks: Vec<PublicKey>,
+        /// Only list outputs of this kind
+        kind: Option<ValueKind>,
+    },
+}
+
+pub fn run(options: &Options, output: &mut Output) -> Result<(), CliError> {
+    match options {
+        Options::List { data, pks, kind } => list(data, pks, *kind, output),
+    }
+}
+
+fn list(
+    data: &str,
+    pks: &[PublicKey],
+    kind: Option<ValueKind>,
+    output: &mut Output,
+) -> Result<(), CliError> {
+    let wallet = DataDir::open(data)?.load()?;
+    let mut listed = 0;
+    for (pk, outputs) in &wallet.unspent_outputs {
+        if !pks.is_empty() && !pks.contains(pk) {
+            continue;
+        }
+        let pk = hex::encode(&pk.serialize()[..]);
+        for ((block_hash, transaction_hash, index), transaction_output) in outputs {
+            let (output_kind, value_cel, id) = match transaction_output.value.get_value() {
+                Ok(value) => (ValueKind::Value, Some(format_cel(value)), None),
+                Err(_) => (
+                    ValueKind::IdTransfer,
+                    None,
+                    transaction_output.value.get_id().map(hex::encode).ok(),
+                ),
+            };
+            if kind.map_or(false, |k| k != output_kind) {
+                continue;
+            }
+            let block_hash = hex::encode(block_hash.hash());
+            let transaction_hash = hex::encode(transaction_hash.hash());
+            let index = index.get_value();
+            let text_value = match (&value_cel, &id) {
+                (Some(value_cel), _) => format!("{} CEL", value_cel),
+                (None, id) => format!("NFT {}", id.as_deref().unwrap_or("?")),
+            };
+            output.emit(
+                "utxo",
+                json!({
+                    "pk": pk,
+                    "block_hash": block_hash,
+                    "transaction_hash": transaction_hash,
+                    "index": index,
+                    "value_cel": value_cel,
+                    "id": id,
+                }),
+                format!(
+                    "{} {}:{}:{} {}",
+                    pk, block_hash, transaction_hash, index, text_value
+                ),
+            );
+            listed += 1;
+        }
+    }
+    output.info(format!("{} unspent outputs", listed));
+    Ok(())
+}
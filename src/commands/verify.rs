@@ -0,0 +1,175 @@
+use crate::{blocks_file::BlocksFile, data_dir::DataDir, error::CliError, output::Output};
+use celestium::{
+    block_hash::BlockHash,
+    merkle_forest::{MerkleForest, HASH_SIZE},
+    transaction::Transaction,
+};
+use colored::*;
+use serde_json::json;
+
+pub struct Options {
+    /// Path to binary blocks file
+    pub blocks: String,
+    /// Path to data dir holding the blocks' transactions
+    pub data: Option<String>,
+}
+
+/// Recomputes the merkle root a block commits to from its transactions.
+pub fn calc_transactions_hash<'a>(
+    transactions: impl Iterator<Item = &'a Transaction>,
+) -> Result<[u8; HASH_SIZE], String> {
+    let leafs = transactions.map(|t| t.hash()).collect();
+    let (_, root) = MerkleForest::new_complete_from_leafs(leafs)?;
+    Ok(root.hash())
+}
+
+pub fn run(options: &Options, output: &mut Output) -> Result<(), CliError> {
+    let blocks_file = BlocksFile::new(&options.blocks);
+
+    let wallet = match &options.data {
+        Some(data_dir) => Some(DataDir::open_read_only(data_dir)?.load()?),
+        None => None,
+    };
+
+    let mut n = 0;
+    let mut errors = 0;
+    let mut invalid_block = None;
+    let mut back_hash: Option<BlockHash> = None;
+    // First and last block of every linked run, all but the first are orphaned
+    let mut segments = vec![(1, 0)];
+    let mut blocks = blocks_file.blocks()?;
+    for entry in blocks.by_ref() {
+        let (block, start) = match entry {
+            Ok(entry) => (entry.block, entry.offset),
+            Err(e) => {
+                invalid_block = Some(e);
+                break;
+            }
+        };
+        n += 1;
+        let mut text = Vec::new();
+        let mined = BlockHash::contains_enough_work(&block.hash().hash());
+        if mined {
+            text.push(format!("Block {} mined ✔️", n).green());
+        } else {
+            text.push(format!("Block {} not mined ❌", n).red());
+            errors += 1;
+        }
+        let linked = match &back_hash {
+            Some(back_hash) if block.back_hash.hash() != back_hash.hash() => {
+                text.push(
+                    format!(
+                        "Block {} links to {}, expected {} ❌",
+                        n,
+                        hex::encode(block.back_hash.hash()),
+                        hex::encode(back_hash.hash())
+                    )
+                    .red(),
+                );
+                errors += 1;
+                segments.push((n, n));
+                false
+            }
+            _ => true,
+        };
+        segments.last_mut().unwrap().1 = n;
+        let transactions_hash_valid = match &wallet {
+            Some(wallet) => match wallet.on_chain_transactions.get(&block.hash()) {
+                Some(transactions) => match calc_transactions_hash(transactions.values()) {
+                    Ok(hash) if hash == block.transactions_hash().hash() => Some(true),
+                    Ok(hash) => {
+                        text.push(
+                            format!(
+                                "Block {} commits to transactions {}, got {} ❌",
+                                n,
+                                hex::encode(block.transactions_hash().hash()),
+                                hex::encode(hash)
+                            )
+                            .red(),
+                        );
+                        Some(false)
+                    }
+                    Err(e) => {
+                        text.push(
+                            format!("Could not hash transactions of block {}. {}", n, e).red(),
+                        );
+                        Some(false)
+                    }
+                },
+                None => {
+                    text.push(format!("Block {} has no transactions in data dir ❌", n).red());
+                    Some(false)
+                }
+            },
+            None => None,
+        };
+        if transactions_hash_valid == Some(false) {
+            errors += 1;
+        }
+        output.emit(
+            "block",
+            json!({
+                "block": n,
+                "offset": start,
+                "hash": hex::encode(block.hash().hash()),
+                "back_hash": hex::encode(block.back_hash.hash()),
+                "mined": mined,
+                "linked": linked,
+                "transactions_hash_valid": transactions_hash_valid,
+            }),
+            text.iter()
+                .map(|line| line.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+        back_hash = Some(block.hash());
+    }
+    if let Some(end_magic) = blocks.end_magic() {
+        output.info(format!("Got blocks end at byte {}", end_magic));
+    }
+
+    output.info("------------------------------------------------------");
+    if segments.len() > 1 {
+        output.info(format!("First broken link at block {}", segments[1].0));
+        for (first, last) in &segments[1..] {
+            output.info(format!("Orphaned segment: blocks {}-{}", first, last));
+        }
+    }
+    let transactions_checked = wallet.is_some();
+    let summary = if errors > 0 {
+        format!("Verification of {} blocks failed with {} errors", n, errors).red()
+    } else if transactions_checked {
+        format!("Verified {} blocks", n).green()
+    } else {
+        format!(
+            "Verified mining and links of {} blocks, transaction hashes not checked without --data",
+            n
+        )
+        .yellow()
+    };
+    output.emit(
+        "summary",
+        json!({
+            "blocks": n,
+            "errors": errors,
+            "first_broken_link": segments.get(1).map(|(first, _)| first),
+            "orphaned_segments": segments[1..]
+                .iter()
+                .map(|(first, last)| json!({ "first": first, "last": last }))
+                .collect::<Vec<_>>(),
+            "valid": errors == 0 && invalid_block.is_none(),
+            "transactions_checked": transactions_checked,
+        }),
+        summary.to_string(),
+    );
+    if let Some(e) = invalid_block {
+        return Err(e);
+    }
+    if errors > 0 {
+        return Err(CliError::Verification(format!(
+            "Found {} errors in {} blocks",
+            errors, n
+        )));
+    }
+    Ok(())
+}
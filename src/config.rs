@@ -0,0 +1,351 @@
+use crate::{error::CliError, read_file};
+use celestium::wallet::{DEFAULT_N_THREADS, DEFAULT_PAR_WORK};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::{env, fmt, path::Path, str::FromStr};
+
+pub const DEFAULT_SERVER_URL: &str = "wss://api.celestium.space";
+
+/// Config file shared by every user of the machine.
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/celestium/config.toml";
+
+/// Pixels `paint` places when neither `--pixels` nor `paint.pixels` is given.
+pub const DEFAULT_PIXELS_PATH: &str = "pixels.json";
+
+/// Backups `collect` keeps of a data dir when `backup.keep` is not set.
+pub const DEFAULT_BACKUP_KEEP: u64 = 10;
+
+/// Config file picked up from the working directory.
+pub const PROJECT_CONFIG_PATH: &str = "celestium.toml";
+
+/// Settings read from TOML config files and `CELESTIUM_*` environment
+/// variables, overridden by command line flags.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Data dir used when a subcommand is not given one
+    pub data_dir: Option<String>,
+    /// Websocket URL of the Celestium server
+    pub server_url: Option<String>,
+    #[serde(default)]
+    pub mining: MiningConfig,
+    #[serde(default)]
+    pub wallet: WalletConfig,
+    #[serde(default)]
+    pub paint: PaintConfig,
+    /// `[paint]` under the name it had before `doit` became `paint`, still
+    /// read for one release
+    #[serde(default, skip_serializing)]
+    doit: Option<PaintConfig>,
+    #[serde(default)]
+    pub backup: BackupConfig,
+}
+
+/// `[mining]` section, sizing the work handed to `Wallet::mine_block` and
+/// `Wallet::mine_transaction`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MiningConfig {
+    /// Number of threads to mine with
+    pub threads: Option<u64>,
+    /// Amount of work handed to each thread at a time
+    pub par_work: Option<u64>,
+}
+
+/// `[wallet]` section, passed on to `Wallet::from_binary`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WalletConfig {
+    /// Rebuild the unspent outputs from the blockchain
+    pub reload_unspent_outputs: Option<bool>,
+    /// Rebuild the NFT lookups from the blockchain
+    pub reload_nft_lookups: Option<bool>,
+    /// Load the wallet without its off chain transactions
+    pub ignore_off_chain_transactions: Option<bool>,
+}
+
+/// `[paint]` section, the identity and pixels of `paint`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PaintConfig {
+    /// Hex public key to mine pixels for
+    pub pk: Option<String>,
+    /// Secret key file to derive the public key from if `pk` is not set
+    pub sk: Option<String>,
+    /// JSON file of pixels to place
+    pub pixels: Option<String>,
+}
+
+/// `[backup]` section, how many backups of a data dir to keep.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BackupConfig {
+    /// Newest backups kept when pruning, older ones are deleted
+    pub keep: Option<u64>,
+}
+
+/// A layer that contributed to a [`Config`].
+#[derive(Serialize, Debug)]
+pub struct ConfigSource {
+    pub layer: &'static str,
+    /// File the layer was read from, `None` for the environment
+    pub path: Option<String>,
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{} ({})", self.layer, path),
+            None => write!(f, "{}", self.layer),
+        }
+    }
+}
+
+/// One layer of the config as read, `config` is `None` for a file that does
+/// not exist.
+pub struct ConfigLayer {
+    pub source: ConfigSource,
+    pub config: Option<Result<Config, CliError>>,
+}
+
+fn env_var<T: FromStr>(name: &str) -> Result<Option<T>, CliError>
+where
+    T::Err: fmt::Display,
+{
+    match env::var(name) {
+        Ok(value) => value.parse().map(Some).map_err(|e| {
+            CliError::Usage(format!("Could not convert {}=\"{}\": {}", name, value, e))
+        }),
+        Err(_) => Ok(None),
+    }
+}
+
+fn warn_deprecated(old: &str, new: &str) {
+    eprintln!(
+        "{} {} is deprecated and will stop being read, use {} instead",
+        "Warning:".yellow(),
+        old,
+        new
+    );
+}
+
+/// Reads `name`, falling back to `old_name` it was renamed from.
+fn renamed_env_var<T: FromStr>(name: &str, old_name: &str) -> Result<Option<T>, CliError>
+where
+    T::Err: fmt::Display,
+{
+    match env_var(name)? {
+        Some(value) => Ok(Some(value)),
+        None => {
+            let value = env_var(old_name)?;
+            if value.is_some() {
+                warn_deprecated(old_name, name);
+            }
+            Ok(value)
+        }
+    }
+}
+
+fn env_bool(name: &str) -> Result<Option<bool>, CliError> {
+    match env::var(name) {
+        Ok(value) => match value.to_lowercase().as_str() {
+            "1" | "true" | "yes" => Ok(Some(true)),
+            "0" | "false" | "no" => Ok(Some(false)),
+            _ => Err(CliError::Usage(format!(
+                "Could not convert {}=\"{}\", expected true or false",
+                name, value
+            ))),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Config, CliError> {
+        let data = read_file(path)?;
+        let data =
+            std::str::from_utf8(&data).map_err(|e| CliError::serialization(path, None, e))?;
+        let mut config: Config =
+            toml::from_str(data).map_err(|e| CliError::serialization(path, None, e))?;
+        if let Some(doit) = config.doit.take() {
+            warn_deprecated(&format!("[doit] in {}", path), "[paint]");
+            config.paint = PaintConfig {
+                pk: config.paint.pk.or(doit.pk),
+                sk: config.paint.sk.or(doit.sk),
+                pixels: config.paint.pixels.or(doit.pixels),
+            };
+        }
+        Ok(config)
+    }
+
+    /// Reads the `CELESTIUM_*` environment variables.
+    pub fn from_env() -> Result<Config, CliError> {
+        Ok(Config {
+            data_dir: env_var("CELESTIUM_DATA_DIR")?,
+            server_url: env_var("CELESTIUM_SERVER_URL")?,
+            mining: MiningConfig {
+                threads: env_var("CELESTIUM_MINING_THREADS")?,
+                par_work: env_var("CELESTIUM_MINING_PAR_WORK")?,
+            },
+            wallet: WalletConfig {
+                reload_unspent_outputs: env_bool("CELESTIUM_WALLET_RELOAD_UNSPENT_OUTPUTS")?,
+                reload_nft_lookups: env_bool("CELESTIUM_WALLET_RELOAD_NFT_LOOKUPS")?,
+                ignore_off_chain_transactions: env_bool(
+                    "CELESTIUM_WALLET_IGNORE_OFF_CHAIN_TRANSACTIONS",
+                )?,
+            },
+            paint: PaintConfig {
+                pk: renamed_env_var("CELESTIUM_PAINT_PK", "CELESTIUM_DOIT_PK")?,
+                sk: renamed_env_var("CELESTIUM_PAINT_SK", "CELESTIUM_DOIT_SK")?,
+                pixels: renamed_env_var("CELESTIUM_PAINT_PIXELS", "CELESTIUM_DOIT_PIXELS")?,
+            },
+            doit: None,
+            backup: BackupConfig {
+                keep: env_var("CELESTIUM_BACKUP_KEEP")?,
+            },
+        })
+    }
+
+    /// `$XDG_CONFIG_HOME/celestium/config.toml`, or under `~/.config` if that
+    /// is not set.
+    pub fn user_path() -> Option<String> {
+        match env::var("XDG_CONFIG_HOME") {
+            Ok(dir) if !dir.is_empty() => Some(format!("{}/celestium/config.toml", dir)),
+            _ => env::var("HOME")
+                .ok()
+                .map(|home| format!("{}/.config/celestium/config.toml", home)),
+        }
+    }
+
+    /// Reads the system, user and project config files, the file given with
+    /// `--config` and the environment, lowest precedence first. Only
+    /// `explicit_path` has to exist.
+    pub fn load_layers(explicit_path: Option<&str>) -> Vec<ConfigLayer> {
+        let mut layers = Vec::new();
+        let files = vec![
+            ("system", Some(SYSTEM_CONFIG_PATH.to_string())),
+            ("user", Config::user_path()),
+            ("project", Some(PROJECT_CONFIG_PATH.to_string())),
+        ];
+        for (layer, path) in files {
+            if let Some(path) = path {
+                let config = if Path::new(&path).exists() {
+                    Some(Config::load(&path))
+                } else {
+                    None
+                };
+                layers.push(ConfigLayer {
+                    source: ConfigSource {
+                        layer,
+                        path: Some(path),
+                    },
+                    config,
+                });
+            }
+        }
+        if let Some(path) = explicit_path {
+            layers.push(ConfigLayer {
+                source: ConfigSource {
+                    layer: "--config",
+                    path: Some(path.to_string()),
+                },
+                config: Some(Config::load(path)),
+            });
+        }
+        layers.push(ConfigLayer {
+            source: ConfigSource {
+                layer: "environment",
+                path: None,
+            },
+            config: Some(Config::from_env()),
+        });
+        layers
+    }
+
+    /// Merges the layers of [`Config::load_layers`], each overriding the ones
+    /// before it, failing on the first that can not be read.
+    pub fn load_layered(
+        explicit_path: Option<&str>,
+    ) -> Result<(Config, Vec<ConfigSource>), CliError> {
+        let mut config = Config::default();
+        let mut sources = Vec::new();
+        for layer in Config::load_layers(explicit_path) {
+            if let Some(layer_config) = layer.config {
+                config = config.merge(layer_config?);
+                sources.push(layer.source);
+            }
+        }
+        Ok((config, sources))
+    }
+
+    /// Overrides the values of `self` with those set in `other`.
+    pub fn merge(self, other: Config) -> Config {
+        Config {
+            data_dir: other.data_dir.or(self.data_dir),
+            server_url: other.server_url.or(self.server_url),
+            mining: MiningConfig {
+                threads: other.mining.threads.or(self.mining.threads),
+                par_work: other.mining.par_work.or(self.mining.par_work),
+            },
+            wallet: WalletConfig {
+                reload_unspent_outputs: other
+                    .wallet
+                    .reload_unspent_outputs
+                    .or(self.wallet.reload_unspent_outputs),
+                reload_nft_lookups: other
+                    .wallet
+                    .reload_nft_lookups
+                    .or(self.wallet.reload_nft_lookups),
+                ignore_off_chain_transactions: other
+                    .wallet
+                    .ignore_off_chain_transactions
+                    .or(self.wallet.ignore_off_chain_transactions),
+            },
+            paint: PaintConfig {
+                pk: other.paint.pk.or(self.paint.pk),
+                sk: other.paint.sk.or(self.paint.sk),
+                pixels: other.paint.pixels.or(self.paint.pixels),
+            },
+            doit: None,
+            backup: BackupConfig {
+                keep: other.backup.keep.or(self.backup.keep),
+            },
+        }
+    }
+
+    /// Fills every unset value but the data dir and `paint` keys with its
+    /// default.
+    pub fn effective(&self) -> Config {
+        let config = self.clone();
+        Config {
+            data_dir: config.data_dir,
+            server_url: config
+                .server_url
+                .or_else(|| Some(DEFAULT_SERVER_URL.to_string())),
+            mining: MiningConfig {
+                threads: config.mining.threads.or(Some(DEFAULT_N_THREADS)),
+                par_work: config.mining.par_work.or(Some(DEFAULT_PAR_WORK)),
+            },
+            wallet: WalletConfig {
+                reload_unspent_outputs: config.wallet.reload_unspent_outputs.or(Some(false)),
+                reload_nft_lookups: config.wallet.reload_nft_lookups.or(Some(false)),
+                ignore_off_chain_transactions: config
+                    .wallet
+                    .ignore_off_chain_transactions
+                    .or(Some(false)),
+            },
+            paint: PaintConfig {
+                pk: config.paint.pk,
+                sk: config.paint.sk,
+                pixels: config
+                    .paint
+                    .pixels
+                    .or_else(|| Some(DEFAULT_PIXELS_PATH.to_string())),
+            },
+            doit: None,
+            backup: BackupConfig {
+                keep: config.backup.keep.or(Some(DEFAULT_BACKUP_KEEP)),
+            },
+        }
+    }
+}
@@ -0,0 +1,587 @@
+use crate::{
+    create_private,
+    error::CliError,
+    key_file::{self, Passphrase},
+    read_file, write_atomically,
+};
+use celestium::{
+    serialize::Serialize,
+    wallet::{BinaryWallet, Wallet},
+};
+use secp256k1::{PublicKey, SecretKey};
+use serde::{Deserialize, Serialize as SerdeSerialize};
+use sha3::{Digest, Sha3_256};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+
+/// Names of the files a wallet is stored as, in the order of the fields of
+/// `BinaryWallet`.
+pub const WALLET_FILES: [&str; 7] = [
+    "blockchain",
+    "pk",
+    "sk",
+    "on_chain_transactions",
+    "unspent_outputs",
+    "nft_lookups",
+    "off_chain_transactions",
+];
+
+/// Directory inside a data dir that a new set of wallet files is written to
+/// before replacing the current one.
+const STAGING_DIR: &str = ".staging";
+
+/// Written to the staging directory once every staged file is durable. Its
+/// presence marks the staged set as committed.
+const MANIFEST_FILE: &str = "MANIFEST";
+
+/// A directory holding a wallet as one binary file per part.
+///
+/// Saving stages all files and commits them with a manifest before moving
+/// them into place, so an interrupted save is either rolled back or completed
+/// the next time the directory is opened, never left half-written.
+pub struct DataDir {
+    path: String,
+    recovery: Option<Recovery>,
+    passphrase: Passphrase,
+}
+
+/// Switches passed on to `Wallet::from_binary`.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct LoadOptions {
+    /// Rebuild the unspent outputs from the blockchain
+    pub reload_unspent_outputs: bool,
+    /// Rebuild the NFT lookups from the blockchain
+    pub reload_nft_lookups: bool,
+    /// Load the wallet without its off chain transactions
+    pub ignore_off_chain_transactions: bool,
+    /// Unlock an encrypted `sk` file, asking for its passphrase. Loads that
+    /// only read the chain leave it locked, see [`DataDir::wallet_from_binary`].
+    pub need_sk: bool,
+}
+
+/// What opening a data dir did about a save that was interrupted.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Recovery {
+    /// The save died before committing, its staged files were discarded
+    RolledBack,
+    /// The save had committed, its remaining staged files were moved into place
+    Completed,
+}
+
+/// Checksums of a committed set of staged files.
+#[derive(SerdeSerialize, Deserialize, Debug)]
+struct Manifest {
+    files: BTreeMap<String, String>,
+}
+
+impl fmt::Display for Recovery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Recovery::RolledBack => write!(f, "Rolled back an interrupted save"),
+            Recovery::Completed => write!(f, "Completed an interrupted save"),
+        }
+    }
+}
+
+fn checksum(data: &[u8]) -> String {
+    hex::encode(Sha3_256::digest(data))
+}
+
+/// Pairs each part of `bin_wallet` with the name of the file it is stored in.
+pub fn wallet_files(bin_wallet: &BinaryWallet) -> [(&'static str, &[u8]); 7] {
+    [
+        (WALLET_FILES[0], &bin_wallet.blockchain_bin),
+        (WALLET_FILES[1], &bin_wallet.pk_bin),
+        (WALLET_FILES[2], &bin_wallet.sk_bin),
+        (WALLET_FILES[3], &bin_wallet.on_chain_transactions_bin),
+        (WALLET_FILES[4], &bin_wallet.unspent_outputs_bin),
+        (WALLET_FILES[5], &bin_wallet.nft_lookups_bin),
+        (WALLET_FILES[6], &bin_wallet.off_chain_transactions_bin),
+    ]
+}
+
+/// Finds the file a wallet that failed to load was rejected for, by
+/// loading it again without each part that can be skipped or rebuilt.
+/// Only `blockchain` and `on_chain_transactions` are left when none of
+/// those loads succeed, and as they are read together the blockchain is
+/// blamed.
+fn failing_part(bin_wallet: &BinaryWallet, options: LoadOptions) -> &'static str {
+    let loads_without = |reload_unspent_outputs, reload_nft_lookups| {
+        Wallet::from_binary(
+            bin_wallet,
+            reload_unspent_outputs || options.reload_unspent_outputs,
+            reload_nft_lookups || options.reload_nft_lookups,
+            true,
+        )
+        .is_ok()
+    };
+    if !options.ignore_off_chain_transactions && loads_without(false, false) {
+        return "off_chain_transactions";
+    }
+    if !options.reload_unspent_outputs && loads_without(true, false) {
+        return "unspent_outputs";
+    }
+    if !options.reload_nft_lookups && loads_without(false, true) {
+        return "nft_lookups";
+    }
+    "blockchain"
+}
+
+impl DataDir {
+    /// Opens the data dir at `path`, first recovering from any save that was
+    /// interrupted.
+    pub fn open(path: &str) -> Result<DataDir, CliError> {
+        let mut data_dir = DataDir {
+            path: path.to_string(),
+            recovery: None,
+            passphrase: Passphrase::default(),
+        };
+        data_dir.recovery = data_dir.recover()?;
+        Ok(data_dir)
+    }
+
+    /// Opens the data dir at `path` without changing anything in it, for
+    /// commands that only read the wallet. Fails if a save was interrupted
+    /// after committing, as the current files are then partly replaced; the
+    /// staged files of a save that never committed are left for the next
+    /// [`DataDir::open`] to discard.
+    pub fn open_read_only(path: &str) -> Result<DataDir, CliError> {
+        let data_dir = DataDir {
+            path: path.to_string(),
+            recovery: None,
+            passphrase: Passphrase::default(),
+        };
+        if Path::new(&data_dir.staging_path(MANIFEST_FILE)).exists() {
+            return Err(CliError::Wallet(format!(
+                "\"{}\" has an interrupted save to complete, \
+                 run a command that writes the wallet first",
+                path
+            )));
+        }
+        Ok(data_dir)
+    }
+
+    /// Creates a data dir at `path` holding `bin_wallet`, such as a freshly
+    /// generated one, refusing to reuse a directory that already holds any
+    /// wallet files.
+    pub fn create(path: &str, bin_wallet: &BinaryWallet) -> Result<DataDir, CliError> {
+        fs::create_dir_all(path).map_err(|e| CliError::io(path, e))?;
+        let data_dir = DataDir::open(path)?;
+        if let Some(name) = WALLET_FILES
+            .iter()
+            .find(|name| Path::new(&data_dir.file_path(name)).exists())
+        {
+            return Err(CliError::Usage(format!(
+                "\"{}\" already holds a wallet ({} exists)",
+                path, name
+            )));
+        }
+        data_dir.save_binary(bin_wallet)?;
+        Ok(data_dir)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// What was done about an interrupted save when the dir was opened.
+    pub fn recovery(&self) -> Option<Recovery> {
+        self.recovery
+    }
+
+    /// Where to get the passphrase from if the `sk` file is encrypted.
+    pub fn set_passphrase(&mut self, passphrase: Passphrase) {
+        self.passphrase = passphrase;
+    }
+
+    pub fn file_path(&self, name: &str) -> String {
+        format!("{}/{}", self.path, name)
+    }
+
+    fn staging_path(&self, name: &str) -> String {
+        format!("{}/{}/{}", self.path, STAGING_DIR, name)
+    }
+
+    /// Wallet files not present in the directory.
+    pub fn missing_files(&self) -> Vec<&'static str> {
+        WALLET_FILES
+            .iter()
+            .copied()
+            .filter(|name| !Path::new(&self.file_path(name)).exists())
+            .collect()
+    }
+
+    /// Reads the seven wallet files, failing on the first one missing.
+    pub fn load_binary(&self) -> Result<BinaryWallet, CliError> {
+        let load = |name: &str| read_file(&self.file_path(name));
+        Ok(BinaryWallet {
+            blockchain_bin: load(WALLET_FILES[0])?,
+            pk_bin: load(WALLET_FILES[1])?,
+            sk_bin: load(WALLET_FILES[2])?,
+            on_chain_transactions_bin: load(WALLET_FILES[3])?,
+            unspent_outputs_bin: load(WALLET_FILES[4])?,
+            nft_lookups_bin: load(WALLET_FILES[5])?,
+            off_chain_transactions_bin: load(WALLET_FILES[6])?,
+        })
+    }
+
+    /// Decodes a wallet previously read with [`DataDir::load_binary`]. The
+    /// key files are checked on their own first, so a corrupt key is reported
+    /// against its file.
+    ///
+    /// An encrypted `sk` file is only unlocked with `options.need_sk`,
+    /// otherwise the wallet gets a placeholder key that signs nothing of
+    /// value. Saving such a wallet keeps the encrypted file.
+    pub fn wallet_from_binary(
+        &self,
+        bin_wallet: &BinaryWallet,
+        options: LoadOptions,
+    ) -> Result<Wallet, CliError> {
+        let unlocked;
+        let bin_wallet = if key_file::is_encrypted(&bin_wallet.sk_bin) {
+            let sk_path = self.file_path("sk");
+            let sk = if options.need_sk {
+                key_file::unlock(&sk_path, &bin_wallet.sk_bin, &self.passphrase)?.0
+            } else {
+                SecretKey::from_slice(&[1; 32]).map_err(|e| CliError::Wallet(e.to_string()))?
+            };
+            unlocked = BinaryWallet {
+                blockchain_bin: bin_wallet.blockchain_bin.clone(),
+                pk_bin: bin_wallet.pk_bin.clone(),
+                sk_bin: key_file::encode(&sk, key_file::KeyFormat::Binary)
+                    .map_err(|e| CliError::serialization(&sk_path, None, e))?,
+                on_chain_transactions_bin: bin_wallet.on_chain_transactions_bin.clone(),
+                unspent_outputs_bin: bin_wallet.unspent_outputs_bin.clone(),
+                nft_lookups_bin: bin_wallet.nft_lookups_bin.clone(),
+                off_chain_transactions_bin: bin_wallet.off_chain_transactions_bin.clone(),
+            };
+            &unlocked
+        } else {
+            bin_wallet
+        };
+        PublicKey::from_serialized(&bin_wallet.pk_bin, &mut 0)
+            .map_err(|e| CliError::serialization(&self.file_path("pk"), Some(0), e))?;
+        SecretKey::from_serialized(&bin_wallet.sk_bin, &mut 0)
+            .map_err(|e| CliError::serialization(&self.file_path("sk"), Some(0), e))?;
+        Wallet::from_binary(
+            bin_wallet,
+            options.reload_unspent_outputs,
+            options.reload_nft_lookups,
+            options.ignore_off_chain_transactions,
+        )
+        .map_err(|e| {
+            let name = failing_part(bin_wallet, options);
+            CliError::serialization(&self.file_path(name), None, e)
+        })
+    }
+
+    /// Loads the wallet, ignoring its off chain transactions and leaving an
+    /// encrypted `sk` file locked.
+    pub fn load(&self) -> Result<Wallet, CliError> {
+        self.wallet_from_binary(
+            &self.load_binary()?,
+            LoadOptions {
+                ignore_off_chain_transactions: true,
+                ..LoadOptions::default()
+            },
+        )
+    }
+
+    /// Replaces the seven wallet files with those of `wallet` as one unit.
+    pub fn save(&self, wallet: &Wallet) -> Result<(), CliError> {
+        self.save_binary(&wallet.to_binary().map_err(CliError::Wallet)?)
+    }
+
+    /// Replaces the seven wallet files with `bin_wallet` as one unit. An
+    /// encrypted `sk` file is never replaced by a plaintext one, and saving a
+    /// wallet with another public key than the one it was encrypted along
+    /// with fails rather than pair the two.
+    pub fn save_binary(&self, bin_wallet: &BinaryWallet) -> Result<(), CliError> {
+        let sk_path = self.file_path("sk");
+        let encrypted_sk =
+            if !key_file::is_encrypted(&bin_wallet.sk_bin) && Path::new(&sk_path).exists() {
+                Some(read_file(&sk_path)?).filter(|sk_bin| key_file::is_encrypted(sk_bin))
+            } else {
+                None
+            };
+        // The pk file is saved along with the encrypted key, so it tells which
+        // public key the key is for without asking for the passphrase
+        if encrypted_sk.is_some() && read_file(&self.file_path("pk"))? != bin_wallet.pk_bin {
+            return Err(CliError::Wallet(format!(
+                "{} is encrypted and belongs to another public key than the wallet being saved, \
+                 decrypt it with `key rekey --decrypt` or move it away first",
+                sk_path
+            )));
+        }
+        let staging_dir = self.file_path(STAGING_DIR);
+        if Path::new(&staging_dir).exists() {
+            fs::remove_dir_all(&staging_dir).map_err(|e| CliError::io(&staging_dir, e))?;
+        }
+        fs::create_dir(&staging_dir).map_err(|e| CliError::io(&staging_dir, e))?;
+        let mut manifest = Manifest {
+            files: BTreeMap::new(),
+        };
+        for (name, data) in wallet_files(bin_wallet).iter() {
+            let data = match &encrypted_sk {
+                Some(sk_bin) if *name == "sk" => sk_bin.as_slice(),
+                _ => *data,
+            };
+            let path = self.staging_path(name);
+            // The key is readable by its owner only, like key files
+            if *name == "sk" {
+                create_private(&path)
+            } else {
+                File::create(&path)
+            }
+            .and_then(|mut f| f.write_all(data).and_then(|_| f.sync_all()))
+            .map_err(|e| CliError::io(&path, e))?;
+            manifest.files.insert(name.to_string(), checksum(data));
+        }
+        let manifest_path = self.staging_path(MANIFEST_FILE);
+        let manifest_bin = serde_json::to_vec(&manifest)
+            .map_err(|e| CliError::serialization(&manifest_path, None, e))?;
+        // Commit point, from here on the staged files replace the current ones
+        write_atomically(&manifest_path, &manifest_bin)
+            .map_err(|e| CliError::io(&manifest_path, e))?;
+        self.install(&manifest)
+    }
+
+    /// Moves the staged files listed in `manifest` into place, skipping those
+    /// already moved, and removes the staging directory.
+    fn install(&self, manifest: &Manifest) -> Result<(), CliError> {
+        for name in manifest.files.keys() {
+            let staged_path = self.staging_path(name);
+            if Path::new(&staged_path).exists() {
+                let path = self.file_path(name);
+                fs::rename(&staged_path, &path).map_err(|e| CliError::io(&path, e))?;
+            }
+        }
+        let dir = self.file_path(".");
+        File::open(&dir)
+            .and_then(|dir| dir.sync_all())
+            .map_err(|e| CliError::io(&dir, e))?;
+        let staging_dir = self.file_path(STAGING_DIR);
+        fs::remove_dir_all(&staging_dir).map_err(|e| CliError::io(&staging_dir, e))
+    }
+
+    /// Finishes or discards a save that did not get to remove its staging
+    /// directory.
+    fn recover(&self) -> Result<Option<Recovery>, CliError> {
+        let staging_dir = self.file_path(STAGING_DIR);
+        if !Path::new(&staging_dir).exists() {
+            return Ok(None);
+        }
+        let manifest_path = self.staging_path(MANIFEST_FILE);
+        if !Path::new(&manifest_path).exists() {
+            fs::remove_dir_all(&staging_dir).map_err(|e| CliError::io(&staging_dir, e))?;
+            return Ok(Some(Recovery::RolledBack));
+        }
+        let manifest: Manifest = serde_json::from_slice(&read_file(&manifest_path)?)
+            .map_err(|e| CliError::serialization(&manifest_path, None, e))?;
+        for (name, expected) in &manifest.files {
+            let staged_path = self.staging_path(name);
+            let path = if Path::new(&staged_path).exists() {
+                staged_path
+            } else {
+                self.file_path(name)
+            };
+            if &checksum(&read_file(&path)?) != expected {
+                return Err(CliError::serialization(
+                    &path,
+                    None,
+                    "Checksum does not match the manifest of the interrupted save",
+                ));
+            }
+        }
+        self.install(&manifest)?;
+        Ok(Some(Recovery::Completed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn bin_wallet(pk: &[u8], sk: &[u8]) -> BinaryWallet {
+        BinaryWallet {
+            blockchain_bin: b"blockchain".to_vec(),
+            pk_bin: pk.to_vec(),
+            sk_bin: sk.to_vec(),
+            on_chain_transactions_bin: b"on_chain_transactions".to_vec(),
+            unspent_outputs_bin: b"unspent_outputs".to_vec(),
+            nft_lookups_bin: b"nft_lookups".to_vec(),
+            off_chain_transactions_bin: b"off_chain_transactions".to_vec(),
+        }
+    }
+
+    fn open(dir: &TempDir) -> DataDir {
+        DataDir::open(dir.path().to_str().unwrap()).unwrap()
+    }
+
+    /// A data dir whose wallet has public key `pk` and an encrypted `sk`.
+    fn encrypted_data_dir(dir: &TempDir, pk: &[u8]) -> (DataDir, Vec<u8>) {
+        let sk = SecretKey::from_slice(&[7; 32]).unwrap();
+        let encrypted_sk = key_file::encrypt(&sk, "passphrase").unwrap();
+        let data_dir = open(dir);
+        data_dir
+            .save_binary(&bin_wallet(pk, &encrypted_sk))
+            .unwrap();
+        (data_dir, encrypted_sk)
+    }
+
+    /// Stages `bin_wallet` as a save would, committing it with a manifest if
+    /// `commit`, without installing anything.
+    fn stage(data_dir: &DataDir, bin_wallet: &BinaryWallet, commit: bool) {
+        fs::create_dir(data_dir.file_path(STAGING_DIR)).unwrap();
+        let mut manifest = Manifest {
+            files: BTreeMap::new(),
+        };
+        for (name, data) in wallet_files(bin_wallet).iter() {
+            fs::write(data_dir.staging_path(name), data).unwrap();
+            manifest.files.insert(name.to_string(), checksum(data));
+        }
+        if commit {
+            fs::write(
+                data_dir.staging_path(MANIFEST_FILE),
+                serde_json::to_vec(&manifest).unwrap(),
+            )
+            .unwrap();
+        }
+    }
+
+    fn assert_wallet(data_dir: &DataDir, expected: &BinaryWallet) {
+        let current = data_dir.load_binary().unwrap();
+        for ((name, data), (_, expected)) in wallet_files(&current)
+            .iter()
+            .zip(wallet_files(expected).iter())
+        {
+            assert_eq!(data, expected, "{}", name);
+        }
+    }
+
+    fn new_wallet() -> BinaryWallet {
+        BinaryWallet {
+            blockchain_bin: b"new blockchain".to_vec(),
+            off_chain_transactions_bin: Vec::new(),
+            ..bin_wallet(b"pk", b"sk")
+        }
+    }
+
+    #[test]
+    fn create_writes_every_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("data");
+        let path = path.to_str().unwrap();
+        let data_dir = DataDir::create(path, &new_wallet()).unwrap();
+        assert!(data_dir.missing_files().is_empty());
+        assert_wallet(&data_dir, &new_wallet());
+        assert!(DataDir::create(path, &new_wallet()).is_err());
+    }
+
+    #[test]
+    fn save_replaces_every_file() {
+        let dir = TempDir::new().unwrap();
+        let data_dir = open(&dir);
+        data_dir.save_binary(&bin_wallet(b"pk", b"sk")).unwrap();
+        data_dir.save_binary(&new_wallet()).unwrap();
+        assert_wallet(&data_dir, &new_wallet());
+        assert!(!Path::new(&data_dir.file_path(STAGING_DIR)).exists());
+    }
+
+    #[test]
+    fn recover_discards_uncommitted_save() {
+        let dir = TempDir::new().unwrap();
+        let old = bin_wallet(b"pk", b"sk");
+        open(&dir).save_binary(&old).unwrap();
+        stage(&open(&dir), &new_wallet(), false);
+
+        let data_dir = open(&dir);
+        assert_eq!(data_dir.recovery(), Some(Recovery::RolledBack));
+        assert_wallet(&data_dir, &old);
+        assert!(!Path::new(&data_dir.file_path(STAGING_DIR)).exists());
+    }
+
+    #[test]
+    fn recover_completes_half_installed_save() {
+        let dir = TempDir::new().unwrap();
+        open(&dir).save_binary(&bin_wallet(b"pk", b"sk")).unwrap();
+        let data_dir = open(&dir);
+        stage(&data_dir, &new_wallet(), true);
+        // The save died after moving the first few files into place
+        for name in &WALLET_FILES[..3] {
+            fs::rename(data_dir.staging_path(name), data_dir.file_path(name)).unwrap();
+        }
+
+        let data_dir = open(&dir);
+        assert_eq!(data_dir.recovery(), Some(Recovery::Completed));
+        assert_wallet(&data_dir, &new_wallet());
+        assert!(!Path::new(&data_dir.file_path(STAGING_DIR)).exists());
+    }
+
+    #[test]
+    fn recover_refuses_checksum_mismatch() {
+        let dir = TempDir::new().unwrap();
+        let old = bin_wallet(b"pk", b"sk");
+        open(&dir).save_binary(&old).unwrap();
+        let data_dir = open(&dir);
+        stage(&data_dir, &new_wallet(), true);
+        let corrupt_path = data_dir.staging_path(WALLET_FILES[4]);
+        fs::write(&corrupt_path, b"corrupt").unwrap();
+
+        match DataDir::open(dir.path().to_str().unwrap()) {
+            Err(CliError::Serialization { path, .. }) => assert_eq!(path, corrupt_path),
+            Err(e) => panic!("Expected a serialization error, got {}", e),
+            Ok(_) => panic!("Expected an error"),
+        }
+        assert_wallet(&data_dir, &old);
+        assert!(Path::new(&data_dir.staging_path(MANIFEST_FILE)).exists());
+    }
+
+    #[test]
+    fn open_read_only_leaves_staging_alone() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().to_str().unwrap();
+        let old = bin_wallet(b"pk", b"sk");
+        open(&dir).save_binary(&old).unwrap();
+        stage(&open(&dir), &new_wallet(), false);
+        let data_dir = DataDir::open_read_only(path).unwrap();
+        assert_eq!(data_dir.recovery(), None);
+        assert_wallet(&data_dir, &old);
+        assert!(Path::new(&data_dir.file_path(STAGING_DIR)).exists());
+
+        fs::remove_dir_all(data_dir.file_path(STAGING_DIR)).unwrap();
+        stage(&data_dir, &new_wallet(), true);
+        assert!(DataDir::open_read_only(path).is_err());
+        assert_wallet(&data_dir, &old);
+    }
+
+    #[test]
+    fn save_keeps_encrypted_sk_of_same_pk() {
+        let dir = TempDir::new().unwrap();
+        let (data_dir, encrypted_sk) = encrypted_data_dir(&dir, b"pk");
+        data_dir
+            .save_binary(&bin_wallet(b"pk", b"plaintext"))
+            .unwrap();
+        assert_eq!(data_dir.load_binary().unwrap().sk_bin, encrypted_sk);
+    }
+
+    #[test]
+    fn save_refuses_pk_of_another_encrypted_sk() {
+        let dir = TempDir::new().unwrap();
+        let (data_dir, encrypted_sk) = encrypted_data_dir(&dir, b"pk");
+        match data_dir.save_binary(&bin_wallet(b"other pk", b"plaintext")) {
+            Err(CliError::Wallet(_)) => {}
+            Err(e) => panic!("Expected a wallet error, got {}", e),
+            Ok(()) => panic!("Expected an error"),
+        }
+        let current = data_dir.load_binary().unwrap();
+        assert_eq!(current.pk_bin, b"pk");
+        assert_eq!(current.sk_bin, encrypted_sk);
+    }
+}
@@ -0,0 +1,112 @@
+use std::{fmt, io};
+
+/// Everything a subcommand can fail with. Each category maps to its own
+/// process exit code, loosely following sysexits(3).
+#[derive(Debug)]
+pub enum CliError {
+    /// The command line could not be interpreted
+    Usage(String),
+    /// A file could not be read or written
+    Io { path: String, source: io::Error },
+    /// A file was read but holds data that could not be decoded
+    Serialization {
+        path: String,
+        offset: Option<usize>,
+        message: String,
+    },
+    /// The wallet rejected an operation
+    Wallet(String),
+    /// The server could not be reached or the connection broke
+    Network(String),
+    /// The server sent something we do not understand
+    Protocol(String),
+    /// The input is well-formed but does not pass verification
+    Verification(String),
+    /// The operating system would not provide something the CLI needs to
+    /// run, like threads
+    System(String),
+}
+
+impl CliError {
+    pub fn io(path: &str, source: io::Error) -> CliError {
+        CliError::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    pub fn serialization<E: ToString>(path: &str, offset: Option<usize>, e: E) -> CliError {
+        CliError::Serialization {
+            path: path.to_string(),
+            offset,
+            message: e.to_string(),
+        }
+    }
+
+    pub fn category(&self) -> &'static str {
+        match self {
+            CliError::Usage(_) => "usage",
+            CliError::Io { .. } => "io",
+            CliError::Serialization { .. } => "serialization",
+            CliError::Wallet(_) => "wallet",
+            CliError::Network(_) => "network",
+            CliError::Protocol(_) => "protocol",
+            CliError::Verification(_) => "verification",
+            CliError::System(_) => "system",
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Verification(_) => 1,
+            CliError::Usage(_) => 64,
+            CliError::Serialization { .. } => 65,
+            CliError::Io { source, .. } if source.kind() == io::ErrorKind::NotFound => 66,
+            CliError::Network(_) => 69,
+            CliError::Wallet(_) => 70,
+            CliError::System(_) => 71,
+            CliError::Io { .. } => 74,
+            CliError::Protocol(_) => 76,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{}", message),
+            CliError::Io { path, source } if source.kind() == io::ErrorKind::NotFound => {
+                write!(f, "\"{}\" does not exist", path)
+            }
+            CliError::Io { path, source } => write!(f, "Could not access \"{}\". {}", path, source),
+            CliError::Serialization {
+                path,
+                offset: Some(offset),
+                message,
+            } => write!(
+                f,
+                "Invalid data at byte {} in \"{}\". {}",
+                offset, path, message
+            ),
+            CliError::Serialization {
+                path,
+                offset: None,
+                message,
+            } => write!(f, "Invalid data in \"{}\". {}", path, message),
+            CliError::Wallet(message) => write!(f, "Wallet: {}", message),
+            CliError::Network(message) => write!(f, "Network: {}", message),
+            CliError::Protocol(message) => write!(f, "Protocol: {}", message),
+            CliError::Verification(message) => write!(f, "{}", message),
+            CliError::System(message) => write!(f, "System: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for CliError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CliError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,468 @@
+use crate::{error::CliError, read_file, write_private_atomically};
+use celestium::serialize::Serialize;
+use chacha20poly1305::{
+    aead::{Aead, NewAead, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use secp256k1::{
+    rand::{thread_rng, RngCore},
+    PublicKey, Secp256k1, SecretKey,
+};
+use std::{env, fmt, fs::File, io::Read, mem::ManuallyDrop, os::unix::io::FromRawFd, str::FromStr};
+
+const ARMOR_BEGIN: &str = "-----BEGIN CELESTIUM SECRET KEY-----";
+const ARMOR_END: &str = "-----END CELESTIUM SECRET KEY-----";
+
+/// Starts every encrypted key file, followed by [`ENCRYPTED_VERSION`].
+const ENCRYPTED_MAGIC: &[u8; 5] = b"CELSK";
+const ENCRYPTED_VERSION: u8 = 1;
+
+/// scrypt cost for new encrypted key files, 32 MiB of memory
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+/// Most scrypt cost accepted from a key file, 256 MiB of memory at the
+/// largest, so a crafted file can not exhaust memory or hang the CLI
+const MAX_SCRYPT_LOG_N: u8 = 18;
+const MAX_SCRYPT_R: u32 = 8;
+const MAX_SCRYPT_P: u32 = 4;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Magic, version, scrypt log_n, r and p, salt and nonce. The whole header is
+/// authenticated along with the encrypted key.
+const ENCRYPTED_HEADER_LEN: usize = 5 + 1 + 1 + 4 + 4 + SALT_LEN + NONCE_LEN;
+
+/// Ways a secret key can be written. Key files are canonically stored as
+/// [`KeyFormat::Binary`], the form `SecretKey::from_serialized` reads and
+/// data dirs hold, or as [`KeyFormat::Encrypted`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum KeyFormat {
+    /// The serialized key bytes
+    Binary,
+    /// The key bytes as hex text, as older versions of `generate` wrote
+    Hex,
+    /// Base64 of the key bytes between PEM-like header and footer lines
+    Armor,
+    /// The key bytes encrypted with ChaCha20-Poly1305 under a key derived
+    /// from a passphrase with scrypt
+    Encrypted,
+}
+
+/// Where to read the passphrase of an encrypted key file from.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Passphrase {
+    /// Ask on the terminal
+    Prompt,
+    /// Read from the named environment variable
+    Env(String),
+    /// Read a line from an open, non-negative file descriptor
+    Fd(i32),
+}
+
+impl Default for Passphrase {
+    fn default() -> Passphrase {
+        Passphrase::Prompt
+    }
+}
+
+impl Passphrase {
+    /// Reads the passphrase, asking with `prompt` if it comes from the
+    /// terminal.
+    pub fn read(&self, prompt: &str) -> Result<String, CliError> {
+        match self {
+            Passphrase::Prompt => rpassword::read_password_from_tty(Some(prompt))
+                .map_err(|e| CliError::io("<tty>", e)),
+            Passphrase::Env(name) => env::var(name)
+                .map_err(|_| CliError::Usage(format!("Passphrase variable {} is not set", name))),
+            Passphrase::Fd(fd) if *fd < 0 => Err(CliError::Usage(format!(
+                "Can not read a passphrase from fd {}",
+                fd
+            ))),
+            Passphrase::Fd(fd) => {
+                // Safety: the descriptor was handed to us to read passphrases
+                // from. It is left open and read a byte at a time, so the
+                // next passphrase is the next line.
+                let file = ManuallyDrop::new(unsafe { File::from_raw_fd(*fd) });
+                let mut line = Vec::new();
+                for byte in (&*file).bytes() {
+                    match byte.map_err(|e| CliError::io(&format!("<fd {}>", fd), e))? {
+                        b'\n' => break,
+                        byte => line.push(byte),
+                    }
+                }
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                String::from_utf8(line).map_err(|e| {
+                    CliError::Usage(format!("Passphrase from fd {} is not UTF-8: {}", fd, e))
+                })
+            }
+        }
+    }
+
+    /// Reads a passphrase to encrypt with, asking twice on the terminal.
+    pub fn read_new(&self) -> Result<String, CliError> {
+        let passphrase = self.read("New passphrase: ")?;
+        if passphrase.is_empty() {
+            return Err(CliError::Usage("The passphrase is empty".to_string()));
+        }
+        if *self == Passphrase::Prompt && self.read("Repeat passphrase: ")? != passphrase {
+            return Err(CliError::Usage("The passphrases do not match".to_string()));
+        }
+        Ok(passphrase)
+    }
+}
+
+impl fmt::Display for KeyFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyFormat::Binary => write!(f, "binary"),
+            KeyFormat::Hex => write!(f, "hex"),
+            KeyFormat::Armor => write!(f, "armor"),
+            KeyFormat::Encrypted => write!(f, "encrypted"),
+        }
+    }
+}
+
+impl FromStr for KeyFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<KeyFormat, String> {
+        match s {
+            "binary" => Ok(KeyFormat::Binary),
+            "hex" => Ok(KeyFormat::Hex),
+            "armor" => Ok(KeyFormat::Armor),
+            "encrypted" => Ok(KeyFormat::Encrypted),
+            _ => Err(format!("Unknown key format \"{}\"", s)),
+        }
+    }
+}
+
+fn to_binary(sk: &SecretKey) -> Result<Vec<u8>, String> {
+    let mut bin = vec![0u8; sk.serialized_len()];
+    sk.serialize_into(&mut bin, &mut 0)?;
+    Ok(bin)
+}
+
+fn from_binary(bin: &[u8]) -> Result<SecretKey, String> {
+    let mut offset = 0;
+    let sk = *SecretKey::from_serialized(bin, &mut offset)?;
+    if offset != bin.len() {
+        return Err(format!(
+            "{} trailing bytes after the key",
+            bin.len() - offset
+        ));
+    }
+    Ok(sk)
+}
+
+/// Writes `sk` in `format`, which can not be [`KeyFormat::Encrypted`], see
+/// [`encrypt`] for that.
+pub fn encode(sk: &SecretKey, format: KeyFormat) -> Result<Vec<u8>, String> {
+    let bin = to_binary(sk)?;
+    Ok(match format {
+        KeyFormat::Encrypted => return Err("Encrypting a key needs a passphrase".to_string()),
+        KeyFormat::Binary => bin,
+        KeyFormat::Hex => format!("{}\n", hex::encode(bin)).into_bytes(),
+        KeyFormat::Armor => {
+            format!("{}\n{}\n{}\n", ARMOR_BEGIN, base64::encode(bin), ARMOR_END).into_bytes()
+        }
+    })
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(ENCRYPTED_MAGIC)
+}
+
+fn derive_key(passphrase: &str, header: &[u8]) -> Result<[u8; 32], String> {
+    let log_n = header[6];
+    let r = u32::from_be_bytes([header[7], header[8], header[9], header[10]]);
+    let p = u32::from_be_bytes([header[11], header[12], header[13], header[14]]);
+    let salt = &header[15..15 + SALT_LEN];
+    if log_n > MAX_SCRYPT_LOG_N || r > MAX_SCRYPT_R || p > MAX_SCRYPT_P {
+        return Err(format!(
+            "scrypt parameters log_n={} r={} p={} exceed the limits log_n={} r={} p={}",
+            log_n, r, p, MAX_SCRYPT_LOG_N, MAX_SCRYPT_R, MAX_SCRYPT_P
+        ));
+    }
+    let params = scrypt::Params::new(log_n, r, p).map_err(|e| e.to_string())?;
+    let mut key = [0u8; 32];
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key).map_err(|e| e.to_string())?;
+    Ok(key)
+}
+
+/// Writes `sk` as an encrypted key file locked with `passphrase`.
+pub fn encrypt(sk: &SecretKey, passphrase: &str) -> Result<Vec<u8>, String> {
+    let mut data = Vec::with_capacity(ENCRYPTED_HEADER_LEN + 48);
+    data.extend_from_slice(ENCRYPTED_MAGIC);
+    data.push(ENCRYPTED_VERSION);
+    data.push(SCRYPT_LOG_N);
+    data.extend_from_slice(&SCRYPT_R.to_be_bytes());
+    data.extend_from_slice(&SCRYPT_P.to_be_bytes());
+    let mut salt_and_nonce = [0u8; SALT_LEN + NONCE_LEN];
+    thread_rng().fill_bytes(&mut salt_and_nonce);
+    data.extend_from_slice(&salt_and_nonce);
+    let key = derive_key(passphrase, &data)?;
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+        .encrypt(
+            Nonce::from_slice(&data[ENCRYPTED_HEADER_LEN - NONCE_LEN..]),
+            Payload {
+                msg: &to_binary(sk)?,
+                aad: &data,
+            },
+        )
+        .map_err(|_| "Could not encrypt key".to_string())?;
+    data.extend_from_slice(&ciphertext);
+    Ok(data)
+}
+
+/// Reads an encrypted key file locked with `passphrase`.
+pub fn decrypt(data: &[u8], passphrase: &str) -> Result<SecretKey, String> {
+    if !is_encrypted(data) || data.len() < ENCRYPTED_HEADER_LEN {
+        return Err("Not an encrypted key file".to_string());
+    }
+    if data[5] != ENCRYPTED_VERSION {
+        return Err(format!(
+            "Unsupported encrypted key file version {}",
+            data[5]
+        ));
+    }
+    let (header, ciphertext) = data.split_at(ENCRYPTED_HEADER_LEN);
+    let key = derive_key(passphrase, header)?;
+    let bin = ChaCha20Poly1305::new(Key::from_slice(&key))
+        .decrypt(
+            Nonce::from_slice(&header[ENCRYPTED_HEADER_LEN - NONCE_LEN..]),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| "Wrong passphrase or corrupt key file".to_string())?;
+    from_binary(&bin)
+}
+
+/// Reads a key written in any [`KeyFormat`] but [`KeyFormat::Encrypted`],
+/// telling which one it was.
+pub fn decode(data: &[u8]) -> Result<(SecretKey, KeyFormat), String> {
+    if is_encrypted(data) {
+        return Err("The key is encrypted".to_string());
+    }
+    let text = std::str::from_utf8(data).ok().map(str::trim);
+    if let Some(armored) = text.filter(|text| text.starts_with(ARMOR_BEGIN)) {
+        let body = armored[ARMOR_BEGIN.len()..]
+            .trim()
+            .strip_suffix(ARMOR_END)
+            .ok_or_else(|| format!("Armored key does not end with {}", ARMOR_END))?;
+        let bin = base64::decode(body.split_whitespace().collect::<String>())
+            .map_err(|e| e.to_string())?;
+        return Ok((from_binary(&bin)?, KeyFormat::Armor));
+    }
+    if let Some(text) = text.filter(|text| {
+        !text.is_empty() && text.len() % 2 == 0 && text.chars().all(|c| c.is_ascii_hexdigit())
+    }) {
+        let bin = hex::decode(text).map_err(|e| e.to_string())?;
+        return Ok((from_binary(&bin)?, KeyFormat::Hex));
+    }
+    Ok((from_binary(data)?, KeyFormat::Binary))
+}
+
+/// Reads `data`, read from `path`, in any [`KeyFormat`], getting the
+/// passphrase from `passphrase` if it is encrypted.
+pub fn unlock(
+    path: &str,
+    data: &[u8],
+    passphrase: &Passphrase,
+) -> Result<(SecretKey, KeyFormat), CliError> {
+    if is_encrypted(data) {
+        let secret = passphrase.read(&format!("Passphrase for {}: ", path))?;
+        let sk = decrypt(data, &secret).map_err(|e| CliError::serialization(path, None, e))?;
+        return Ok((sk, KeyFormat::Encrypted));
+    }
+    decode(data).map_err(|e| CliError::serialization(path, None, e))
+}
+
+/// Reads the secret key file at `path` in any [`KeyFormat`].
+pub fn read(path: &str, passphrase: &Passphrase) -> Result<(SecretKey, KeyFormat), CliError> {
+    unlock(path, &read_file(path)?, passphrase)
+}
+
+/// Writes `sk` to `path` in the canonical binary format, encrypted if a
+/// passphrase is given.
+pub fn write(path: &str, sk: &SecretKey, passphrase: Option<&str>) -> Result<(), CliError> {
+    let data = match passphrase {
+        Some(passphrase) => encrypt(sk, passphrase),
+        None => encode(sk, KeyFormat::Binary),
+    }
+    .map_err(|e| CliError::serialization(path, None, e))?;
+    write_private_atomically(path, &data).map_err(|e| CliError::io(path, e))
+}
+
+/// The public key of `sk` as hex, the form `--pk` flags take.
+pub fn pk_hex(sk: &SecretKey) -> String {
+    let pk = PublicKey::from_secret_key(&Secp256k1::new(), sk);
+    hex::encode(&pk.serialize()[..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, os::unix::fs::PermissionsExt};
+    use tempfile::TempDir;
+
+    const PASSPHRASE: &str = "correct horse battery staple";
+
+    fn sk() -> SecretKey {
+        SecretKey::from_slice(&[7; 32]).unwrap()
+    }
+
+    fn assert_same_key(a: &SecretKey, b: &SecretKey) {
+        assert_eq!(to_binary(a).unwrap(), to_binary(b).unwrap());
+    }
+
+    #[test]
+    fn encrypt_round_trip() {
+        let data = encrypt(&sk(), PASSPHRASE).unwrap();
+        assert!(is_encrypted(&data));
+        assert_same_key(&decrypt(&data, PASSPHRASE).unwrap(), &sk());
+    }
+
+    #[test]
+    fn encryptions_differ_in_salt_and_nonce() {
+        let a = encrypt(&sk(), PASSPHRASE).unwrap();
+        let b = encrypt(&sk(), PASSPHRASE).unwrap();
+        assert_ne!(a[15..ENCRYPTED_HEADER_LEN], b[15..ENCRYPTED_HEADER_LEN]);
+    }
+
+    #[test]
+    fn wrong_passphrase() {
+        let data = encrypt(&sk(), PASSPHRASE).unwrap();
+        assert_eq!(
+            decrypt(&data, "wrong").unwrap_err(),
+            "Wrong passphrase or corrupt key file"
+        );
+    }
+
+    #[test]
+    fn tampered_header_or_ciphertext() {
+        let data = encrypt(&sk(), PASSPHRASE).unwrap();
+        // scrypt log_n, salt, nonce and ciphertext
+        for &i in [6, 15, ENCRYPTED_HEADER_LEN - 1, data.len() - 1].iter() {
+            let mut tampered = data.clone();
+            tampered[i] ^= 0x01;
+            assert_eq!(
+                decrypt(&tampered, PASSPHRASE).unwrap_err(),
+                "Wrong passphrase or corrupt key file",
+                "byte {} flipped",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_or_not_encrypted() {
+        let data = encrypt(&sk(), PASSPHRASE).unwrap();
+        assert_eq!(
+            decrypt(&data[..ENCRYPTED_HEADER_LEN - 1], PASSPHRASE).unwrap_err(),
+            "Not an encrypted key file"
+        );
+        assert!(decrypt(&data[..data.len() - 1], PASSPHRASE).is_err());
+        let bin = encode(&sk(), KeyFormat::Binary).unwrap();
+        assert_eq!(
+            decrypt(&bin, PASSPHRASE).unwrap_err(),
+            "Not an encrypted key file"
+        );
+    }
+
+    #[test]
+    fn unknown_version() {
+        let mut data = encrypt(&sk(), PASSPHRASE).unwrap();
+        data[5] = ENCRYPTED_VERSION + 1;
+        assert_eq!(
+            decrypt(&data, PASSPHRASE).unwrap_err(),
+            format!(
+                "Unsupported encrypted key file version {}",
+                ENCRYPTED_VERSION + 1
+            )
+        );
+    }
+
+    #[test]
+    fn scrypt_parameters_over_caps() {
+        let data = encrypt(&sk(), PASSPHRASE).unwrap();
+        let mut log_n = data.clone();
+        log_n[6] = MAX_SCRYPT_LOG_N + 1;
+        let mut r = data.clone();
+        r[7..11].copy_from_slice(&(MAX_SCRYPT_R + 1).to_be_bytes());
+        let mut p = data;
+        p[11..15].copy_from_slice(&(MAX_SCRYPT_P + 1).to_be_bytes());
+        for tampered in [log_n, r, p].iter() {
+            assert!(decrypt(tampered, PASSPHRASE)
+                .unwrap_err()
+                .starts_with("scrypt parameters"));
+        }
+    }
+
+    #[test]
+    fn detects_plaintext_formats() {
+        let bin = encode(&sk(), KeyFormat::Binary).unwrap();
+        for &format in [KeyFormat::Binary, KeyFormat::Hex, KeyFormat::Armor].iter() {
+            let (decoded, detected) = decode(&encode(&sk(), format).unwrap()).unwrap();
+            assert_eq!(detected, format);
+            assert_same_key(&decoded, &sk());
+        }
+        // Hex as older versions wrote it, without a trailing newline
+        let (decoded, detected) = decode(hex::encode(&bin).as_bytes()).unwrap();
+        assert_eq!(detected, KeyFormat::Hex);
+        assert_same_key(&decoded, &sk());
+        assert!(!is_encrypted(&bin));
+        assert_eq!(
+            decode(&encrypt(&sk(), PASSPHRASE).unwrap()).unwrap_err(),
+            "The key is encrypted"
+        );
+    }
+
+    #[test]
+    fn trailing_bytes_after_key() {
+        let mut bin = encode(&sk(), KeyFormat::Binary).unwrap();
+        bin.push(0);
+        assert_eq!(decode(&bin).unwrap_err(), "1 trailing bytes after the key");
+    }
+
+    #[test]
+    fn rekey_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sk").to_str().unwrap().to_string();
+        let old = Passphrase::Env("CELESTIUM_TEST_OLD_PASSPHRASE".to_string());
+        let new = Passphrase::Env("CELESTIUM_TEST_NEW_PASSPHRASE".to_string());
+        env::set_var("CELESTIUM_TEST_OLD_PASSPHRASE", "old");
+        env::set_var("CELESTIUM_TEST_NEW_PASSPHRASE", "new");
+
+        write(&path, &sk(), Some("old")).unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        let (read_sk, format) = read(&path, &old).unwrap();
+        assert_eq!(format, KeyFormat::Encrypted);
+        write(&path, &read_sk, Some("new")).unwrap();
+        assert!(read(&path, &old).is_err());
+        assert_same_key(&read(&path, &new).unwrap().0, &sk());
+
+        write(&path, &sk(), None).unwrap();
+        let (read_sk, format) = read(&path, &new).unwrap();
+        assert_eq!(format, KeyFormat::Binary);
+        assert_same_key(&read_sk, &sk());
+    }
+
+    #[test]
+    fn negative_passphrase_fd() {
+        match Passphrase::Fd(-1).read("") {
+            Err(CliError::Usage(_)) => {}
+            Err(e) => panic!("Expected a usage error, got {}", e),
+            Ok(_) => panic!("Expected an error"),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Library behind the Celestium command line interface, so the subcommands
+//! and the file formats and protocols they work with can be reused from other
+//! Rust services.
+
+pub mod backup;
+pub mod blocks_file;
+pub mod commands;
+pub mod config;
+pub mod data_dir;
+pub mod error;
+pub mod key_file;
+pub mod output;
+pub mod pixel;
+pub mod pixel_client;
+
+use error::CliError;
+use secp256k1::PublicKey;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::Path,
+};
+
+pub const DUST_PER_CEL: u128 = 10_000_000_000_000_000_000_000_000_000_000;
+
+/// Formats an amount of dust as CEL with every decimal written out.
+pub fn format_cel(dust: u128) -> String {
+    format!("{}.{:031}", dust / DUST_PER_CEL, dust % DUST_PER_CEL)
+}
+
+/// Parses an amount of CEL with up to 31 decimals into dust, exactly.
+pub fn parse_cel(amount: &str) -> Result<u128, CliError> {
+    let invalid =
+        |reason: &str| CliError::Usage(format!("Invalid amount \"{}\": {}", amount, reason));
+    let (whole, fraction) = match amount.find('.') {
+        Some(i) => (&amount[..i], &amount[i + 1..]),
+        None => (amount, ""),
+    };
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(invalid("no digits"));
+    }
+    if !whole
+        .chars()
+        .chain(fraction.chars())
+        .all(|c| c.is_ascii_digit())
+    {
+        return Err(invalid("expected decimal digits"));
+    }
+    if fraction.len() > 31 {
+        return Err(invalid("more than 31 decimals"));
+    }
+    let whole: u128 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().map_err(|_| invalid("too large"))?
+    };
+    let fraction: u128 = format!("{:0<31}", fraction)
+        .parse()
+        .map_err(|_| invalid("too large"))?;
+    whole
+        .checked_mul(DUST_PER_CEL)
+        .and_then(|dust| dust.checked_add(fraction))
+        .ok_or_else(|| invalid("too large"))
+}
+
+/// Parses a compressed public key written as hex.
+pub fn parse_pk(pk_hex: &str) -> Result<PublicKey, CliError> {
+    hex::decode(pk_hex)
+        .map_err(|e| e.to_string())
+        .and_then(|pk| PublicKey::from_slice(&pk).map_err(|e| e.to_string()))
+        .map_err(|e| CliError::Usage(format!("Invalid public key \"{}\": {}", pk_hex, e)))
+}
+
+pub fn read_file(path: &str) -> Result<Vec<u8>, CliError> {
+    fs::read(path).map_err(|e| CliError::io(path, e))
+}
+
+/// Replaces `path` with `data` by writing a temporary sibling, syncing it and
+/// renaming it into place, so readers see either the old or the new file.
+pub fn write_atomically(path: &str, data: &[u8]) -> io::Result<()> {
+    let tmp_path = format!("{}.tmp", path);
+    let f = File::create(&tmp_path)?;
+    replace_with(f, &tmp_path, path, data)
+}
+
+/// Like [`write_atomically`], but only the owner can read or write the file,
+/// for secret keys.
+pub fn write_private_atomically(path: &str, data: &[u8]) -> io::Result<()> {
+    let tmp_path = format!("{}.tmp", path);
+    let f = create_private(&tmp_path)?;
+    replace_with(f, &tmp_path, path, data)
+}
+
+/// Creates or truncates `path` with mode 0600, also tightening the mode of a
+/// file that already existed.
+pub fn create_private(path: &str) -> io::Result<File> {
+    let f = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    f.set_permissions(fs::Permissions::from_mode(0o600))?;
+    Ok(f)
+}
+
+fn replace_with(mut f: File, tmp_path: &str, path: &str, data: &[u8]) -> io::Result<()> {
+    f.write_all(data)?;
+    f.sync_all()?;
+    fs::rename(tmp_path, path)?;
+    sync_parent_dir(path)
+}
+
+/// Syncs the directory holding `path`, making a rename into it durable.
+pub fn sync_parent_dir(path: &str) -> io::Result<()> {
+    match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(amount: &str) -> String {
+        match parse_cel(amount) {
+            Err(CliError::Usage(message)) => message,
+            Err(e) => panic!("Expected a usage error, got {}", e),
+            Ok(dust) => panic!("Parsed \"{}\" as {} dust", amount, dust),
+        }
+    }
+
+    #[test]
+    fn parses_every_decimal() {
+        assert_eq!(parse_cel("1").unwrap(), DUST_PER_CEL);
+        assert_eq!(parse_cel("1.").unwrap(), DUST_PER_CEL);
+        assert_eq!(parse_cel(".5").unwrap(), DUST_PER_CEL / 2);
+        assert_eq!(parse_cel("0.0000000000000000000000000000001").unwrap(), 1);
+        assert_eq!(
+            parse_cel("12.3456789012345678901234567890123").unwrap(),
+            123_456_789_012_345_678_901_234_567_890_123
+        );
+    }
+
+    #[test]
+    fn rejects_more_than_31_decimals() {
+        assert!(parse_error("0.00000000000000000000000000000001").contains("31 decimals"));
+    }
+
+    #[test]
+    fn rejects_no_digits() {
+        assert!(parse_error(".").contains("no digits"));
+        assert!(parse_error("").contains("no digits"));
+    }
+
+    #[test]
+    fn rejects_signs_and_other_characters() {
+        for amount in ["+1", "-1", "1e3", "1,5", " 1", "1.2.3"].iter() {
+            assert!(parse_error(amount).contains("expected decimal digits"));
+        }
+    }
+
+    #[test]
+    fn rejects_overflow() {
+        let max = format_cel(u128::MAX);
+        assert_eq!(max, "34028236.6920938463463374607431768211455");
+        assert_eq!(parse_cel(&max).unwrap(), u128::MAX);
+        assert!(parse_error("34028236.6920938463463374607431768211456").contains("too large"));
+        assert!(parse_error("34028237").contains("too large"));
+        assert!(parse_error("340282366920938463463374607431768211456").contains("too large"));
+    }
+
+    #[test]
+    fn format_parse_round_trip() {
+        for &dust in [
+            0,
+            1,
+            DUST_PER_CEL - 1,
+            DUST_PER_CEL,
+            7 * DUST_PER_CEL / 4,
+            u128::MAX,
+        ]
+        .iter()
+        {
+            assert_eq!(parse_cel(&format_cel(dust)).unwrap(), dust);
+        }
+        assert_eq!(format_cel(1), "0.0000000000000000000000000000001");
+        assert_eq!(
+            format_cel(DUST_PER_CEL),
+            "1.0000000000000000000000000000000"
+        );
+    }
+}
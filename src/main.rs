@@ -1,255 +1,37 @@
-use celestium::{
-    block::Block,
-    block_hash::BlockHash,
-    serialize::{DynamicSized, Serialize},
-    transaction::Transaction,
-    transaction_output::TransactionOutput,
-    transaction_value::TransactionValue,
-    wallet::{
-        self, BinaryWallet, Wallet, DEFAULT_N_THREADS, DEFAULT_PAR_WORK,
-        DEFAULT_PROGRESSBAR_TEMPLATE,
-    },
-};
 #[macro_use]
 extern crate clap;
-use colored::*;
-use image::{io::Reader as ImageReader, GenericImageView, ImageFormat, Rgba, RgbaImage};
-use mongodb::bson::{doc, oid::ObjectId};
-use probability::{self, distribution::Sample};
-use rand::seq::SliceRandom;
-use rand::thread_rng;
-use rayon::ThreadPoolBuilder;
-use secp256k1::{PublicKey, Secp256k1, SecretKey};
-use serde::Deserialize;
-use sha3::{Digest, Sha3_224, Sha3_256};
-use std::{
-    cmp::{max, min},
-    fs::read,
-};
-use std::{
-    collections::HashMap,
-    io::{self, Write},
-};
-use std::{fs::File, time::Instant};
-use websocket::{sync::client::ClientBuilder, ws::dataframe::DataFrame, Message};
-
-use indicatif::{ProgressBar, ProgressStyle};
-use std::{
-    env,
-    fs::{self, OpenOptions},
+use celestium::wallet::{DEFAULT_N_THREADS, DEFAULT_PAR_WORK};
+use celestium_cli::{
+    commands::{
+        self, backup, balance, bench, collect, count, generate, inspect, key, mine, paint, piximg,
+        random, send, utxo, verify,
+    },
+    config::{Config, DEFAULT_BACKUP_KEEP, DEFAULT_PIXELS_PATH, DEFAULT_SERVER_URL},
+    data_dir::LoadOptions,
+    error::CliError,
+    key_file::{self, KeyFormat, Passphrase},
+    output::{Format, Output},
+    parse_cel, parse_pk,
 };
-use std::{fs::remove_file, io::prelude::*};
-
-macro_rules! unwrap_or_print {
-    ($result: expr, $format_string: expr) => {
-        match $result {
-            Ok(r) => r,
-            Err(e) => {
-                println!($format_string, e);
-                return;
-            }
-        }
-    };
-}
-
-#[derive(Deserialize, Debug)]
-struct Data {
-    data: Vec<[u16; 3]>,
-}
-
-const PIXEL_HASH_SIZE: usize = 28;
-const NUM_COLORS: u8 = 56;
-const DUST_PER_CEL: u128 = 10_000_000_000_000_000_000_000_000_000_000;
-
-const COLOR_MAP: [[u8; 4]; 57] = [
-    [0x00, 0x00, 0x00, 0xff],
-    [0xe5, 0x00, 0x00, 0xff],
-    [0x02, 0xbe, 0x01, 0xff],
-    [0x00, 0x00, 0xea, 0xff],
-    [0xf8, 0xf2, 0x08, 0xff],
-    [0xfd, 0x5e, 0xf8, 0xff],
-    [0x00, 0xd3, 0xdd, 0xff],
-    [0xff, 0xff, 0xff, 0xff],
-    [0x74, 0x15, 0xcd, 0xff],
-    [0xf3, 0xc9, 0x9d, 0xff],
-    [0x99, 0x99, 0x99, 0xff],
-    [0xe5, 0x95, 0x00, 0xff],
-    [0x00, 0x83, 0xc7, 0xff],
-    [0x34, 0x71, 0x15, 0xff],
-    [0x43, 0x27, 0x0a, 0xff],
-    [0x86, 0x5a, 0x48, 0xff],
-    // Leet h4cker colors
-    [0xc5, 0x00, 0x00, 0xff],
-    [0xff, 0x40, 0x40, 0xff],
-    [0x00, 0x9e, 0x00, 0xff],
-    [0x42, 0xfe, 0x41, 0xff],
-    [0x00, 0x00, 0xca, 0xff],
-    [0x40, 0x40, 0xff, 0xff],
-    [0xc5, 0xb9, 0x00, 0xff],
-    [0xff, 0xff, 0x40, 0xff],
-    [0xdd, 0x3e, 0xd8, 0xff],
-    [0xff, 0x9e, 0xff, 0xff],
-    [0x00, 0xb3, 0xbd, 0xff],
-    [0x40, 0xff, 0xff, 0xff],
-    [0x54, 0x00, 0xad, 0xff],
-    [0xb4, 0x55, 0xff, 0xff],
-    [0xd3, 0xa9, 0x7d, 0xff],
-    [0xff, 0xff, 0xdd, 0xff],
-    [0x79, 0x79, 0x79, 0xff],
-    [0xd9, 0xd9, 0xd9, 0xff],
-    [0xc5, 0x75, 0x00, 0xff],
-    [0xff, 0xd5, 0x40, 0xff],
-    [0x00, 0x63, 0xa7, 0xff],
-    [0x40, 0xc3, 0xff, 0xff],
-    [0x14, 0x51, 0x00, 0xff],
-    [0x74, 0xb1, 0x55, 0xff],
-    [0x23, 0x07, 0x00, 0xff],
-    [0x83, 0x67, 0x4a, 0xff],
-    [0x66, 0x3a, 0x28, 0xff],
-    [0xc6, 0x9a, 0x88, 0xff],
-    [0x11, 0x11, 0x11, 0xff],
-    [0x22, 0x22, 0x22, 0xff],
-    [0x33, 0x33, 0x33, 0xff],
-    [0x44, 0x44, 0x44, 0xff],
-    [0x55, 0x55, 0x55, 0xff],
-    [0x66, 0x66, 0x66, 0xff],
-    [0x77, 0x77, 0x77, 0xff],
-    [0x88, 0x88, 0x88, 0xff],
-    [0xaa, 0xaa, 0xaa, 0xff],
-    [0xbb, 0xbb, 0xbb, 0xff],
-    [0xcc, 0xcc, 0xcc, 0xff],
-    [0xdd, 0xdd, 0xdd, 0xff],
-    [0xee, 0xee, 0xee, 0xff],
-];
-
-#[allow(non_snake_case)]
-#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
-struct StoreItem {
-    _id: ObjectId,
-    // a: String,
-    // A1: String,
-    // A2: String,
-    // A3: String,
-    // ad: String,
-    // albedo: Option<f64>,
-    // BV: String,
-    // class: String,
-    // closeness: Option<i32>,
-    // condition_code: String,
-    // data_arc: f64,
-    // diameter: Option<f64>,
-    // diameter_sigma: Option<f64>,
-    // DT: String,
-    // dv: f64,
-    // e: f64,
-    // epoch: f64,
-    // epoch_cal: f64,
-    // epoch_mjd: f64,
-    // equinox: String,
-    // est_diameter: f64,
-    // extent: String,
-    // first_obs: String,
-    full_name: String,
-    // G: String,
-    // GM: String,
-    // H: String,
-    // H_sigma: Option<f64>,
-    // i: f64,
-    // id: String,
-    // inexact: bool,
-    // IR: String,
-    // K1: String,
-    // K2: String,
-    // last_obs: String,
-    // M1: String,
-    // M2: String,
-    // ma: String,
-    // moid: String,
-    // moid_jup: String,
-    // moid_ld: String,
-    // n: String,
-    // n_del_obs_used: String,
-    // n_dop_obs_used: String,
-    // n_obs_used: f64,
-    // name: String,
-    // neo: String,
-    // om: f64,
-    // orbit_id: String,
-    // PC: String,
-    // pdes: String,
-    // per: String,
-    // per_y: String,
-    // pha: String,
-    // prefix: String,
-    price: Option<f64>,
-    // producer: String,
-    profit: Option<f64>,
-    // prov_des: String,
-    // q: f64,
-    // rms: String,
-    // rot_per: Option<f64>,
-    // saved: f64,
-    // score: f64,
-    // sigma_a: String,
-    // sigma_ad: String,
-    // sigma_e: String,
-    // sigma_i: String,
-    // sigma_ma: String,
-    // sigma_n: String,
-    // sigma_om: String,
-    // sigma_per: String,
-    // sigma_q: String,
-    // sigma_tp: String,
-    // sigma_w: String,
-    // spec: String,
-    // spec_B: String,
-    // spec_T: String,
-    // spkid: f64,
-    // t_jup: String,
-    // tp: f64,
-    // tp_cal: f64,
-    // two_body: String,
-    // UB: String,
-    // w: f64,
-    store_value_in_dust: String,
-    id_hash: String,
-}
-
-fn calc_pixel_hash(
-    x: u16,
-    y: u16,
-    color: u8,
-    back_hash: [u8; PIXEL_HASH_SIZE],
-) -> [u8; PIXEL_HASH_SIZE] {
-    let mut to_digest = [0u8; 33];
-    to_digest[..PIXEL_HASH_SIZE].copy_from_slice(&back_hash);
-    to_digest[PIXEL_HASH_SIZE] = (x >> 8) as u8;
-    to_digest[PIXEL_HASH_SIZE + 1] = (x & 0xff) as u8;
-    to_digest[PIXEL_HASH_SIZE + 2] = (y >> 8) as u8;
-    to_digest[PIXEL_HASH_SIZE + 3] = (y & 0xff) as u8;
-    to_digest[PIXEL_HASH_SIZE + 4] = color as u8;
-    let mut hash = [0u8; PIXEL_HASH_SIZE];
-    hash.copy_from_slice(&Sha3_224::digest(&to_digest));
-    hash
-}
-
-fn diff(r: (u8, u8)) -> u32 {
-    if r.0 > r.1 {
-        (r.0 - r.1) as u32
-    } else {
-        (r.1 - r.0) as u32
-    }
-}
+use clap::ArgMatches;
+use secp256k1::{PublicKey, Secp256k1};
+use std::process;
 
 fn main() {
     let matches = clap_app!(myapp =>
         (author: "Artificial Mind A/S <jhs@artificialmind.ai>")
         (about: "Celestium Command Line Interface")
+        (@arg format: +global +takes_value -f --format possible_value[text json ndjson] default_value("text") "Output format")
+        (@arg config: +global +takes_value --config "Path to TOML config file, overriding the system, user and project ones")
         (@subcommand generate =>
             (about: "Generates a new test blockchain")
             (@arg blocks: +required +takes_value -b --blocks "Path to save binary blocks file to")
             (@arg sk: +required +takes_value -s --secret "Path to save secret key file to")
             (@arg count: +required +takes_value -c --count "Amount of unmined blocks to generate")
+            (@arg data: +takes_value -i --data "Path to create a data dir holding the generated wallet in")
+            (@arg encrypt: --encrypt "Encrypt the secret key with a passphrase, asked for unless given below")
+            (@arg new_passphrase_env: +takes_value --("new-passphrase-env") requires[encrypt] conflicts_with[new_passphrase_fd] "Environment variable holding the passphrase to encrypt with")
+            (@arg new_passphrase_fd: +takes_value --("new-passphrase-fd") requires[encrypt] "File descriptor to read the passphrase to encrypt with from")
         )
         (@subcommand random =>
             (about: "Generates random z-vectors from noisy images")
@@ -262,810 +44,679 @@ fn main() {
             (about: "Mines a binary blocks file")
             (@arg blocks: +required +takes_value -b --blocks "Path to binary blocks file")
             (@arg sk: +required +takes_value -s --sk "Path to binary Secret Key file")
+            (@arg resume: -r --resume conflicts_with[restart] "Continue from the last checkpoint recorded in the journal")
+            (@arg restart: --restart "Discard the checkpoint of an interrupted run and mine from the start")
+            (@arg rechain: --rechain "Re-link and re-mine every block from the first broken link onward")
+            (@arg threads: +takes_value -t --threads "Number of threads to mine with")
+            (@arg par_work: +takes_value --("par-work") "Amount of work handed to each thread at a time")
+            (@arg passphrase_env: +takes_value --("passphrase-env") conflicts_with[passphrase_fd] "Environment variable holding the passphrase of an encrypted secret key")
+            (@arg passphrase_fd: +takes_value --("passphrase-fd") "File descriptor to read the passphrase of an encrypted secret key from")
+        )
+        (@subcommand bench =>
+            (about: "Measures mining speed across thread counts and par-work sizes")
+            (@arg threads: +takes_value +use_delimiter -t --threads "Comma separated thread counts to try")
+            (@arg par_work: +takes_value +use_delimiter --("par-work") "Comma separated par-work sizes to try")
+            (@arg samples: +takes_value -n --samples default_value("5") "Blocks and transactions to mine per configuration")
+            (@arg output: +takes_value -o --output "Path to save results as JSON to")
         )
         (@subcommand verify =>
-            (about: "Verifies a binary blocks file for completed work")
+            (about: "Verifies a binary blocks file for completed work and a consistent chain")
             (@arg blocks: +required +takes_value -b --blocks "Path to binary blocks file")
+            (@arg data: +takes_value -i --data "Path to data dir holding the blocks' transactions, needed to check transaction hashes")
+        )
+        (@subcommand inspect =>
+            (about: "Prints blocks or transactions as a tree")
+            (@arg SELECTOR: "Index, counting from 1, or hash of the block or transaction to show")
+            (@arg blocks: +takes_value -b --blocks conflicts_with[transactions] "Path to binary blocks file")
+            (@arg transactions: +takes_value --transactions "Path to file of serialized transactions")
+            (@arg data: +takes_value -i --data "Path to data dir, to show its off chain transactions or the transactions of --blocks")
         )
         (@subcommand count =>
             (about: "Count IDs")
-            (@arg data: +required +takes_value -i --data "Path to data dir")
+            (@arg data: +takes_value -i --data "Path to data dir, defaults to data_dir from the config")
+            (@arg reload_unspent_outputs: --("reload-unspent-outputs") conflicts_with[no_reload_unspent_outputs] "Rebuild the unspent outputs from the blockchain")
+            (@arg no_reload_unspent_outputs: --("no-reload-unspent-outputs") "Use the stored unspent outputs, even if the config says to rebuild them")
+            (@arg reload_nft_lookups: --("reload-nft-lookups") conflicts_with[no_reload_nft_lookups] "Rebuild the NFT lookups from the blockchain")
+            (@arg no_reload_nft_lookups: --("no-reload-nft-lookups") "Use the stored NFT lookups, even if the config says to rebuild them")
+            (@arg ignore_off_chain_transactions: --("ignore-off-chain-transactions") conflicts_with[no_ignore_off_chain_transactions] "Load the wallet without its off chain transactions")
+            (@arg no_ignore_off_chain_transactions: --("no-ignore-off-chain-transactions") "Load the off chain transactions, even if the config says to ignore them")
         )
         (@subcommand collect =>
             (about: "Collect off chain transactions into block")
-            (@arg data: +required +takes_value -i --data "Path to data dir")
+            (@arg data: +takes_value -i --data "Path to data dir, defaults to data_dir from the config")
+            (@arg reload_unspent_outputs: --("reload-unspent-outputs") conflicts_with[no_reload_unspent_outputs] "Rebuild the unspent outputs from the blockchain")
+            (@arg no_reload_unspent_outputs: --("no-reload-unspent-outputs") "Use the stored unspent outputs, even if the config says to rebuild them")
+            (@arg reload_nft_lookups: --("reload-nft-lookups") conflicts_with[no_reload_nft_lookups] "Rebuild the NFT lookups from the blockchain")
+            (@arg no_reload_nft_lookups: --("no-reload-nft-lookups") "Use the stored NFT lookups, even if the config says to rebuild them")
+            (@arg ignore_off_chain_transactions: --("ignore-off-chain-transactions") conflicts_with[no_ignore_off_chain_transactions] "Load the wallet without its off chain transactions")
+            (@arg no_ignore_off_chain_transactions: --("no-ignore-off-chain-transactions") "Load the off chain transactions, even if the config says to ignore them")
+            (@arg no_backup: --("no-backup") "Skip backing up the data dir before changing it")
+            (@arg keep_backups: +takes_value --("keep-backups") "Newest backups to keep, defaults to backup.keep from the config or 10")
+            (@arg passphrase_env: +takes_value --("passphrase-env") conflicts_with[passphrase_fd] "Environment variable holding the passphrase of an encrypted secret key")
+            (@arg passphrase_fd: +takes_value --("passphrase-fd") "File descriptor to read the passphrase of an encrypted secret key from")
+            (@arg dry_run: --("dry-run") "Preview the blocks that would be created without writing anything")
+            (@arg only: +takes_value --only possible_value[pixel transfer] "Only collect pixel base transactions or value transfers")
+            (@arg pk: +takes_value +multiple number_of_values(1) --pk "Only collect transactions paying to or spending from this hex public key")
+            (@arg skip_newest: +takes_value --("skip-newest") default_value("0") "Leave this many of the newest off chain transactions for a later run")
+            (@arg max_transactions: +takes_value --("max-transactions") "Most transactions per block")
+            (@arg max_bytes: +takes_value --("max-bytes") "Most serialized transaction bytes per block")
+            (@arg max_blocks: +takes_value --("max-blocks") "Most blocks to create, by default as many as needed")
+            (@arg mine: -m --mine "Mine each block before adding it to the wallet")
+            (@arg threads: +takes_value -t --threads "Number of threads to mine with")
+            (@arg par_work: +takes_value --("par-work") "Amount of work handed to each thread at a time")
+        )
+        (@subcommand backup =>
+            (about: "Manages the backups collect takes of a data dir")
+            (@setting SubcommandRequiredElseHelp)
+            (@subcommand list =>
+                (about: "Lists the backups of a data dir")
+                (@arg data: +takes_value -i --data "Path to data dir, defaults to data_dir from the config")
+            )
+            (@subcommand restore =>
+                (about: "Replaces the wallet files of a data dir with those of a backup")
+                (@arg data: +takes_value -i --data "Path to data dir, defaults to data_dir from the config")
+                (@arg ID: +required "Backup to restore, as shown by backup list")
+                (@arg keep: +takes_value --keep "Newest backups to keep, defaults to backup.keep from the config or 10")
+            )
+            (@subcommand prune =>
+                (about: "Deletes all but the newest backups of a data dir")
+                (@arg data: +takes_value -i --data "Path to data dir, defaults to data_dir from the config")
+                (@arg keep: +takes_value --keep "Newest backups to keep, defaults to backup.keep from the config or 10")
+            )
+        )
+        (@subcommand balance =>
+            (about: "Sums the unspent outputs of a public key")
+            (@arg data: +takes_value -i --data "Path to data dir, defaults to data_dir from the config")
+            (@arg pk: +takes_value --pk "Hex public key to sum the unspent outputs of, defaults to the wallet's own")
+        )
+        (@subcommand utxo =>
+            (about: "Inspects the unspent outputs of a data dir")
+            (@setting SubcommandRequiredElseHelp)
+            (@subcommand list =>
+                (about: "Lists unspent outputs with their block hash, transaction hash, index and value")
+                (@arg data: +takes_value -i --data "Path to data dir, defaults to data_dir from the config")
+                (@arg pk: +takes_value +multiple number_of_values(1) --pk "Only list outputs owned by this hex public key")
+                (@arg kind: +takes_value --kind possible_value[value id-transfer] "Only list outputs of this kind")
+            )
+        )
+        (@subcommand send =>
+            (about: "Builds, signs and mines a transaction sending CEL to a public key")
+            (@arg data: +takes_value -i --data "Path to data dir, defaults to data_dir from the config")
+            (@arg sk: +takes_value -s --sk "Path to binary Secret Key file to send from, defaults to the data dir's")
+            (@arg to: +required +takes_value --to "Hex public key to send to")
+            (@arg amount: +required +takes_value --amount "Amount of CEL to send, with up to 31 decimals")
+            (@arg output: +takes_value -o --output "Path to write the mined transaction to instead of adding it to the off chain transactions")
+            (@arg passphrase_env: +takes_value --("passphrase-env") conflicts_with[passphrase_fd] "Environment variable holding the passphrase of an encrypted secret key")
+            (@arg passphrase_fd: +takes_value --("passphrase-fd") "File descriptor to read the passphrase of an encrypted secret key from")
+            (@arg threads: +takes_value -t --threads "Number of threads to mine with")
+            (@arg par_work: +takes_value --("par-work") "Amount of work handed to each thread at a time")
+        )
+        (@subcommand key =>
+            (about: "Creates, shows and converts secret key files")
+            (@setting SubcommandRequiredElseHelp)
+            (@subcommand new =>
+                (about: "Generates a secret key file")
+                (@arg FILE: +required "Path to write the secret key file to")
+                (@arg encrypt: --encrypt "Encrypt the secret key with a passphrase, asked for unless given below")
+                (@arg new_passphrase_env: +takes_value --("new-passphrase-env") requires[encrypt] conflicts_with[new_passphrase_fd] "Environment variable holding the passphrase to encrypt with")
+                (@arg new_passphrase_fd: +takes_value --("new-passphrase-fd") requires[encrypt] "File descriptor to read the passphrase to encrypt with from")
+            )
+            (@subcommand show =>
+                (about: "Prints the hex public key of a secret key file")
+                (@arg FILE: +required "Path to secret key file")
+                (@arg passphrase_env: +takes_value --("passphrase-env") conflicts_with[passphrase_fd] "Environment variable holding the passphrase of an encrypted secret key")
+                (@arg passphrase_fd: +takes_value --("passphrase-fd") "File descriptor to read the passphrase of an encrypted secret key from")
+            )
+            (@subcommand import =>
+                (about: "Converts a hex, binary, armored or encrypted key to a secret key file")
+                (@arg INPUT: +required "Path to key to import, - for stdin")
+                (@arg FILE: +required "Path to write the secret key file to")
+                (@arg passphrase_env: +takes_value --("passphrase-env") conflicts_with[passphrase_fd] "Environment variable holding the passphrase of an encrypted secret key")
+                (@arg passphrase_fd: +takes_value --("passphrase-fd") "File descriptor to read the passphrase of an encrypted secret key from")
+                (@arg encrypt: --encrypt "Encrypt the secret key with a passphrase, asked for unless given below")
+                (@arg new_passphrase_env: +takes_value --("new-passphrase-env") requires[encrypt] conflicts_with[new_passphrase_fd] "Environment variable holding the passphrase to encrypt with")
+                (@arg new_passphrase_fd: +takes_value --("new-passphrase-fd") requires[encrypt] "File descriptor to read the passphrase to encrypt with from")
+            )
+            (@subcommand export =>
+                (about: "Writes a secret key file as hex, binary or armored text")
+                (@arg FILE: +required "Path to secret key file")
+                (@arg key_format: +takes_value --("key-format") possible_value[hex binary armor] default_value("armor") "Format to export the key in")
+                (@arg output: +takes_value -o --output "Path to write the key to, defaults to stdout")
+                (@arg passphrase_env: +takes_value --("passphrase-env") conflicts_with[passphrase_fd] "Environment variable holding the passphrase of an encrypted secret key")
+                (@arg passphrase_fd: +takes_value --("passphrase-fd") "File descriptor to read the passphrase of an encrypted secret key from")
+            )
+            (@subcommand rekey =>
+                (about: "Changes the passphrase of a secret key file")
+                (@arg FILE: +required "Path to secret key file")
+                (@arg passphrase_env: +takes_value --("passphrase-env") conflicts_with[passphrase_fd] "Environment variable holding the passphrase of an encrypted secret key")
+                (@arg passphrase_fd: +takes_value --("passphrase-fd") "File descriptor to read the passphrase of an encrypted secret key from")
+                (@arg decrypt: --decrypt conflicts_with[new_passphrase_env new_passphrase_fd] "Store the key unencrypted instead")
+                (@arg new_passphrase_env: +takes_value --("new-passphrase-env") conflicts_with[new_passphrase_fd] "Environment variable holding the new passphrase")
+                (@arg new_passphrase_fd: +takes_value --("new-passphrase-fd") "File descriptor to read the new passphrase from")
+            )
         )
         (@subcommand piximg =>
             (about: "Creates a video from pixel transactions on the Celestium blockchain")
             (@arg FILE: +required +takes_value -i "Path to off chain transactions file")
             (@arg DIRECTORY: +required +takes_value -o "Path to save frames of video")
         )
-        (@subcommand doit =>
-            (about: "Does it")
+        (@subcommand paint =>
+            (about: "Mines and submits pixels from an image or a JSON file of pixels")
+            (alias: "doit")
+            (@arg server: +takes_value --server "Websocket URL of the Celestium server")
+            (@arg pk: +takes_value --pk conflicts_with[sk] "Hex public key to mine pixels for")
+            (@arg sk: +takes_value -s --sk "Path to secret key file to derive the public key to mine pixels for from")
+            (@arg pixels: +takes_value --pixels conflicts_with[image] "Path to JSON file of pixels to place, defaults to pixels.json")
+            (@arg image: +takes_value --image requires[at] "Path to image to quantize to the palette and paint")
+            (@arg at: +takes_value +use_delimiter --at number_of_values(2) requires[image] "X,Y canvas position of the top left corner of the image")
+            (@arg dither: --dither requires[image] "Dither the image while quantizing it")
+            (@arg threads: +takes_value -t --threads "Number of threads to mine with")
+            (@arg par_work: +takes_value --("par-work") "Amount of work handed to each thread at a time")
+            (@arg passphrase_env: +takes_value --("passphrase-env") conflicts_with[passphrase_fd] "Environment variable holding the passphrase of an encrypted secret key")
+            (@arg passphrase_fd: +takes_value --("passphrase-fd") "File descriptor to read the passphrase of an encrypted secret key from")
         )
-    )
-    .get_matches();
-
-    if let Some(matches) = matches.subcommand_matches("generate") {
-        match Wallet::generate_init_blockchain() {
-            Ok(wallet) => {
-                println!("Generated {} blocks, serializing", wallet.count_blocks());
-                let serialized_blocks = wallet.serialize_blockchain().unwrap();
-                let sk = wallet.get_sk().unwrap();
-                let blocks_path = matches.value_of("blocks").unwrap();
-                let sk_path = matches.value_of("sk").unwrap();
-                remove_file(blocks_path)
-                    .unwrap_or_else(|e| println!("Warning: Could not clean blocks file. {}", e));
-                remove_file(sk_path).unwrap_or_else(|e| {
-                    println!("Warning: Could not clean secret key file. {}", e)
-                });
-                let mut blocks_f = OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .open(blocks_path)
-                    .expect("Error: Could not create blocks file");
-                let mut sk_f = OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .open(sk_path)
-                    .expect("Error: Could not create secret key file");
-                blocks_f
-                    .write_all(&serialized_blocks)
-                    .expect("Error: Could not write to blocks file");
-                println!("{:?}", sk);
-                sk_f.write_all(sk.to_string().as_bytes())
-                    .expect("Error: Could not write to file");
-                blocks_f.flush().expect("Error: Could not flush file");
-                println!("Done.")
-            }
-            Err(e) => {
-                println!("Error generating blocks: {}", e);
-            }
-        }
-    } else if let Some(matches) = matches.subcommand_matches("random") {
-        let random_images_location = matches.value_of("images").unwrap();
-        let dir: Vec<_> = unwrap_or_print!(
-            fs::read_dir(random_images_location),
-            "Could not open dir: {}"
+        (@subcommand config =>
+            (about: "Inspects the layered config")
+            (@setting SubcommandRequiredElseHelp)
+            (@subcommand show =>
+                (about: "Prints the effective config and the files it was merged from")
+            )
+            (@subcommand path =>
+                (about: "Lists the config files looked for and whether each could be read")
+            )
         )
-        .collect();
-        println!("Found {} images", dir.len());
-        let image_width = 1280usize; // TODO: Get from images
-        let image_height = 720usize; // TODO: Get from images
-        let mut all_img_pixels: Vec<Vec<Vec<Rgba<u8>>>> =
-            vec![vec![Vec::new(); image_width]; image_height];
-        for entry in dir {
-            let entry = unwrap_or_print!(entry, "Could not read entry: {}").path();
-            println!("Parsing image {:?}", entry);
-            let img = unwrap_or_print!(ImageReader::open(entry), "Courd not open image: {}")
-                .decode()
-                .unwrap();
-            for (y, inner) in all_img_pixels.iter_mut().enumerate().take(image_width) {
-                for (x, pixel) in inner.iter_mut().enumerate().take(image_height) {
-                    pixel.push(img.get_pixel(x as u32, y as u32));
-                }
-            }
-        }
-        let mut img = RgbaImage::new(image_width as u32, image_height as u32);
-        let mut diffs: Vec<(u32, u32, u32, u32, u16, u16)> =
-            vec![(0, 0, 0, 0, 0, 0); image_width * image_height];
-        for (y, inner) in all_img_pixels.iter().enumerate() {
-            for (x, pixels) in inner.iter().enumerate() {
-                let mut r_total_diff = 0;
-                let mut g_total_diff = 0;
-                let mut b_total_diff = 0;
-                let mut a_total_diff = 0;
-                for pixel1 in pixels {
-                    for pixel2 in pixels {
-                        if pixel1 != pixel2 {
-                            r_total_diff += diff((pixel1.0[0], pixel2.0[0]));
-                            g_total_diff += diff((pixel1.0[1], pixel2.0[1]));
-                            b_total_diff += diff((pixel1.0[2], pixel2.0[2]));
-                            a_total_diff += diff((pixel1.0[3], pixel2.0[3]));
-                        }
-                    }
-                }
-                diffs[x + y * image_width] = (
-                    r_total_diff,
-                    g_total_diff,
-                    b_total_diff,
-                    a_total_diff,
-                    x as u16,
-                    y as u16,
-                );
-            }
+    )
+    .get_matches_safe()
+    .unwrap_or_else(|e| match e.kind {
+        clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed => e.exit(),
+        _ => {
+            eprintln!("{}", e.message);
+            process::exit(CliError::Usage(e.message).exit_code())
         }
+    });
 
-        diffs.sort_by(|&(r0, g0, b0, a0, _, _), &(r1, g1, b1, a1, _, _)| {
-            ((r1 as usize) + (g1 as usize) + (b1 as usize) + (a1 as usize))
-                .partial_cmp(&((r0 as usize) + (g0 as usize) + (b0 as usize) + (a0 as usize)))
-                .unwrap()
-        });
-
-        let count = value_t!(matches.value_of("count"), usize).unwrap_or_else(|e| {
-            println!("Could not convert count param: {}", e);
-            e.exit();
-        });
-
-        let top_diffs = &diffs[..min(count, diffs.len())];
-
-        let mut max_r_diff = u32::MIN;
-        let mut min_r_diff = u32::MAX;
-        let mut max_g_diff = u32::MIN;
-        let mut min_g_diff = u32::MAX;
-        let mut max_b_diff = u32::MIN;
-        let mut min_b_diff = u32::MAX;
-        let mut max_a_diff = u32::MIN;
-        let mut min_a_diff = u32::MAX;
+    let format = match matches.value_of("format") {
+        Some("json") => Format::Json,
+        Some("ndjson") => Format::Ndjson,
+        _ => Format::Text,
+    };
+    let (command, matches) = match matches.subcommand() {
+        (command, Some(matches)) => (command, matches),
+        _ => return,
+    };
 
-        for (r, g, b, a, _, _) in top_diffs {
-            max_r_diff = max(max_r_diff, *r);
-            min_r_diff = min(min_r_diff, *r);
-            max_g_diff = max(max_g_diff, *g);
-            min_g_diff = min(min_g_diff, *g);
-            max_b_diff = max(max_b_diff, *b);
-            min_b_diff = min(min_b_diff, *b);
-            max_a_diff = max(max_a_diff, *a);
-            min_a_diff = min(min_a_diff, *a);
-        }
+    let mut output = Output::new(format, command);
+    let result = run(command, matches, &mut output);
+    if let Err(e) = &result {
+        output.error(e);
+    }
+    output.finish();
+    if let Err(e) = result {
+        process::exit(e.exit_code());
+    }
+}
 
-        let mut normalized_diffs: Vec<(u8, u8, u8, u8, u16, u16)> =
-            vec![(0, 0, 0, 0, 0, 0); top_diffs.len()];
-        for (i, (r, g, b, _, x, y)) in top_diffs.iter().enumerate() {
-            let r = (((*r - min_r_diff) as f64 / (max_r_diff as f64)) * 255.0) as u8;
-            let g = (((*g - min_g_diff) as f64 / (max_g_diff as f64)) * 255.0) as u8;
-            let b = (((*b - min_b_diff) as f64 / (max_b_diff as f64)) * 255.0) as u8;
-            let a = 255u8;
-            img.put_pixel(*x as u32, *y as u32, Rgba([r, g, b, a]));
-            normalized_diffs[i] = (r, g, b, a, *x, *y);
-        }
-        img.save_with_format("output.png", ImageFormat::Png)
-            .unwrap();
+/// Reads a positive number from `name`, falling back to `config` and then
+/// `default`.
+fn positive_arg(
+    matches: &ArgMatches,
+    name: &str,
+    config: Option<u64>,
+    default: u64,
+) -> Result<u64, CliError> {
+    let value = match matches.value_of(name) {
+        Some(_) => value_t!(matches.value_of(name), u64)
+            .map_err(|e| CliError::Usage(format!("Could not convert {} param: {}", name, e)))?,
+        None => config.unwrap_or(default),
+    };
+    if value == 0 {
+        return Err(CliError::Usage(format!(
+            "--{} must be at least 1",
+            name.replace('_', "-")
+        )));
+    }
+    Ok(value)
+}
 
-        let size = value_t!(matches.value_of("size"), usize).unwrap_or_else(|e| {
-            println!("Could not convert size param: {}", e);
-            e.exit();
-        });
-        println!("Generating {} z-vectors", count);
-        let distribution = probability::distribution::Gaussian::new(0.0, 1.0);
-        let z_vectors: Vec<Vec<_>> = normalized_diffs
-            .iter()
-            .map(|diff| {
-                let mut seed = probability::source::Default::new().seed([
-                    ((diff.0 as u64) << 24)
-                        + ((diff.1 as u64) << 16)
-                        + ((diff.2 as u64) << 8)
-                        + (diff.3 as u64),
-                    ((diff.4 as u64) << 16) + (diff.5 as u64),
-                ]);
-                (0..size)
-                    .map(move |_| distribution.sample(&mut seed))
-                    .collect()
-            })
-            .collect();
+/// Reads where to get a passphrase from, `<prefix>_env` or `<prefix>_fd`,
+/// asking on the terminal if neither was given.
+fn passphrase_arg(matches: &ArgMatches, prefix: &str) -> Result<Passphrase, CliError> {
+    let env_name = format!("{}_env", prefix);
+    let fd_name = format!("{}_fd", prefix);
+    if let Some(name) = matches.value_of(&env_name) {
+        return Ok(Passphrase::Env(name.to_string()));
+    }
+    match matches.value_of(&fd_name) {
+        Some(fd) => match fd.parse::<i32>() {
+            Ok(fd) if fd >= 0 => Ok(Passphrase::Fd(fd)),
+            _ => Err(CliError::Usage(format!(
+                "Could not convert {} param: \"{}\" is not a file descriptor",
+                fd_name, fd
+            ))),
+        },
+        None => Ok(Passphrase::Prompt),
+    }
+}
 
-        let mut bin = vec![0u8; count * size * 8 + 4 * count];
-        println!("Total len {}", bin.len());
-        let mut i = 0;
-        for z_vector in z_vectors {
-            for sample in z_vector {
-                bin[i..i + 8].copy_from_slice(&sample.to_be_bytes());
-                i += 8;
-            }
-        }
-        println!("Z-Vector cut off, coords from here on: {}", i);
-        for (x, y) in normalized_diffs[..count]
-            .iter()
-            .map(|diff| (diff.4, diff.5))
-            .collect::<Vec<(u16, u16)>>()
-        {
-            bin[i] = (x >> 8) as u8;
-            bin[i + 1] = (x & 0xff) as u8;
-            bin[i + 2] = (y >> 8) as u8;
-            bin[i + 3] = (y & 0xff) as u8;
-            i += 4;
-        }
-        let output_path = matches.value_of("output").unwrap();
-        let mut f = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(output_path)
-            .unwrap();
-        println!("Saving {} z-vectors to '{}'", count, output_path);
-        f.write_all(&bin).unwrap();
-        f.flush().unwrap();
-    } else if let Some(matches) = matches.subcommand_matches("verify") {
-        let serialized_blocks_location = matches.value_of("blocks").unwrap();
+/// Reads where to get the passphrase of a key to encrypt from, `None` if it
+/// is not to be encrypted.
+fn encrypt_arg(matches: &ArgMatches) -> Result<Option<Passphrase>, CliError> {
+    if matches.is_present("encrypt") {
+        passphrase_arg(matches, "new_passphrase").map(Some)
+    } else {
+        Ok(None)
+    }
+}
 
-        let mut file = OpenOptions::new()
-            .read(true)
-            .open(serialized_blocks_location)
-            .unwrap();
-        let mut serialized_blocks = Vec::new();
-        file.read_to_end(&mut serialized_blocks).unwrap();
+/// The key `paint` mines for, from `--pk` or `--sk`, then `paint.pk` or
+/// `paint.sk` from the config.
+fn paint_pk(matches: &ArgMatches, config: &Config) -> Result<PublicKey, CliError> {
+    let from_sk = |path: &str| -> Result<PublicKey, CliError> {
+        let (sk, _) = key_file::read(path, &passphrase_arg(matches, "passphrase")?)?;
+        Ok(PublicKey::from_secret_key(&Secp256k1::new(), &sk))
+    };
+    match (matches.value_of("pk"), matches.value_of("sk")) {
+        (Some(pk), _) => parse_pk(pk),
+        (None, Some(sk)) => from_sk(sk),
+        (None, None) => match (&config.paint.pk, &config.paint.sk) {
+            (Some(pk), _) => parse_pk(pk),
+            (None, Some(sk)) => from_sk(sk),
+            (None, None) => Err(CliError::Usage(
+                "No public key to mine for, give --pk or --sk or set paint.pk or paint.sk in the config"
+                    .to_string(),
+            )),
+        },
+    }
+}
 
-        let mut i = 0;
-        let mut n = 0;
-        while i < serialized_blocks.len() {
-            n += 1;
-            if serialized_blocks[i] == 0x41
-                && serialized_blocks[i + 1] == 0x41
-                && serialized_blocks[i + 2] == 0x41
-                && serialized_blocks[i + 3] == 0x41
-            {
-                println!(
-                    "Got blocks end at byte {}-{} ({:x?})",
-                    i,
-                    i + 4,
-                    &serialized_blocks[i..i + 4]
-                );
-                break;
-            }
-            match Block::from_serialized(&serialized_blocks, &mut i) {
-                Ok(block) => {
-                    if BlockHash::contains_enough_work(&block.hash().hash()) {
-                        println!("{}", format!("Block {} mined ✔️", n).green());
-                    } else {
-                        println!("{}", format!("Block {} not mined ❌", n).red());
-                    }
-                }
-                Err(s) => {
-                    println!("Got invalid block at {}. {}", i, s);
-                    break;
-                }
-            };
-        }
-    } else if let Some(matches) = matches.subcommand_matches("mine") {
-        let serialized_blocks_location = matches.value_of("blocks").unwrap();
-        let serialized_sk_location = matches.value_of("sk").unwrap();
+/// Reads a positive number from `name` if it was given.
+fn optional_positive_arg(matches: &ArgMatches, name: &str) -> Result<Option<usize>, CliError> {
+    match matches.value_of(name) {
+        Some(_) => Ok(Some(positive_arg(matches, name, None, 1)? as usize)),
+        None => Ok(None),
+    }
+}
 
-        let mut file = OpenOptions::new()
-            .read(true)
-            .open(serialized_blocks_location)
-            .unwrap();
-        let mut unmined_serialized_blocks = Vec::new();
-        file.read_to_end(&mut unmined_serialized_blocks).unwrap();
+/// Reads a comma separated list of positive numbers from `name`, falling
+/// back to `default`.
+fn positive_args(
+    matches: &ArgMatches,
+    name: &str,
+    default: Vec<u64>,
+) -> Result<Vec<u64>, CliError> {
+    let values = match matches.values_of(name) {
+        Some(_) => values_t!(matches.values_of(name), u64)
+            .map_err(|e| CliError::Usage(format!("Could not convert {} param: {}", name, e)))?,
+        None => default,
+    };
+    if values.contains(&0) {
+        return Err(CliError::Usage(format!(
+            "--{} must be at least 1",
+            name.replace('_', "-")
+        )));
+    }
+    Ok(values)
+}
 
-        let mut file = OpenOptions::new()
-            .read(true)
-            .open(serialized_sk_location)
-            .unwrap();
-        let mut serialized_sk = Vec::new();
-        file.read_to_end(&mut serialized_sk).unwrap();
+/// Powers of two up to the number of CPUs, and the number of CPUs itself.
+fn default_thread_sweep() -> Vec<u64> {
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get()) as u64;
+    let mut threads: Vec<u64> = (0..)
+        .map(|i| 1u64 << i)
+        .take_while(|&threads| threads < cpus)
+        .collect();
+    threads.push(cpus);
+    threads
+}
 
-        let secp = Secp256k1::new();
-        let sk = *SecretKey::from_serialized(&serialized_sk, &mut 0).unwrap();
-        let pk = PublicKey::from_secret_key(&secp, &sk);
-        let wallet = Wallet::new(pk, sk).unwrap();
+/// Reads the data dir from `--data`, falling back to the config.
+fn data_dir_arg(matches: &ArgMatches, config: &Config) -> Result<String, CliError> {
+    matches
+        .value_of("data")
+        .map(String::from)
+        .or_else(|| config.data_dir.clone())
+        .ok_or_else(|| {
+            CliError::Usage(
+                "No data dir given, pass --data or set data_dir in the config".to_string(),
+            )
+        })
+}
 
-        let mut unmined_blocks = Vec::default();
-        let mut total_blocks = 0;
-        let mut i = 0;
-        let mut unmined_serialized_blocks_len = unmined_serialized_blocks.len();
-        let mut end_magic = Vec::new();
-        while i < unmined_serialized_blocks.len() {
-            if unmined_serialized_blocks[i] == 0x41
-                && unmined_serialized_blocks[i + 1] == 0x41
-                && unmined_serialized_blocks[i + 2] == 0x41
-                && unmined_serialized_blocks[i + 3] == 0x41
-            {
-                println!(
-                    "Got blocks end at byte {}-{} ({:x?})",
-                    i,
-                    i + 4,
-                    &unmined_serialized_blocks[i..i + 4]
-                );
-                end_magic = unmined_serialized_blocks[i..].to_vec();
-                unmined_serialized_blocks_len = i;
-                break;
-            }
-            match Block::from_serialized(&unmined_serialized_blocks, &mut i) {
-                Ok(block) => unmined_blocks.push(*block),
-                Err(s) => {
-                    println!("Got invalid block at {}. {}", total_blocks, s);
-                    break;
-                }
-            };
-            total_blocks += 1;
+/// Reads the `Wallet::from_binary` switches, each `--x` or `--no-x` flag
+/// overriding what the config says.
+fn load_options(matches: &ArgMatches, config: &Config) -> LoadOptions {
+    let flag = |name: &str, config: Option<bool>| {
+        if matches.is_present(name) {
+            true
+        } else if matches.is_present(&format!("no_{}", name)) {
+            false
+        } else {
+            config.unwrap_or(false)
         }
-        let mut back_hash = unmined_blocks[0].hash();
-        let mut mined_blocks = Vec::default();
-        let mut mined_serialized_blocks_len = 0;
-        println!(
-            "Found {} blocks ({}B), starting mining",
-            total_blocks, unmined_serialized_blocks_len
-        );
-        for (n, mut block) in unmined_blocks.clone().iter_mut().enumerate() {
-            if BlockHash::contains_enough_work(&block.hash().hash()) {
-                println!(
-                    "{}",
-                    format!("Block {}/{} already mined ✔️", n + 1, total_blocks).green(),
-                );
-                mined_blocks.push(block.clone());
-                continue;
-            }
-            print!("Mining block {}/{}", n + 1, total_blocks);
-            io::stdout().flush().unwrap();
-            block.back_hash = back_hash;
-            let block_hash = block.hash();
-            let start = Instant::now();
-            let mined_block = wallet.mine_block(DEFAULT_N_THREADS, DEFAULT_PAR_WORK, block.clone());
-            back_hash = BlockHash::from(block_hash);
-            match mined_block {
-                Ok(mined_block) => {
-                    mined_serialized_blocks_len += mined_block.serialized_len();
-                    unmined_serialized_blocks_len -= block.serialized_len();
-                    mined_blocks.push(*mined_block.clone());
-                    println!("{}", ". Done ✔️".green());
-                }
-                Err(e) => println!(". Got none block. {}", e),
-            }
-            println!("Time: {:?}", start.elapsed());
-
-            let mut len = 0;
-            let mut all_blocks_serialized =
-                vec![
-                    0u8;
-                    mined_serialized_blocks_len + unmined_serialized_blocks_len + end_magic.len()
-                ];
-            for (block_n, i_block) in mined_blocks.iter_mut().enumerate() {
-                i_block
-                    .serialize_into(&mut all_blocks_serialized, &mut len)
-                    .unwrap_or_else(|e| {
-                        panic!("Error: Could not serialize block {}. {}", block_n, e)
-                    });
-            }
-            for (block_n, i_block) in unmined_blocks[n + 1..].iter().enumerate() {
-                i_block
-                    .serialize_into(&mut all_blocks_serialized, &mut len)
-                    .unwrap_or_else(|e| {
-                        panic!(
-                            "Error: Could not serialize block {}. {}",
-                            n + 1 + block_n,
-                            e
-                        )
-                    });
-            }
-            all_blocks_serialized[len..].copy_from_slice(end_magic.as_slice());
-            remove_file(serialized_blocks_location).unwrap_or_else(|e| {
-                println!(
-                    "Warning: Could not clean \"{:?}\". {}",
-                    serialized_blocks_location, e
-                )
-            });
-            println!(
-                "Saving checkpoint ({}B) to {:?}",
-                mined_serialized_blocks_len + unmined_serialized_blocks_len,
-                serialized_blocks_location
-            );
+    };
+    LoadOptions {
+        reload_unspent_outputs: flag(
+            "reload_unspent_outputs",
+            config.wallet.reload_unspent_outputs,
+        ),
+        reload_nft_lookups: flag("reload_nft_lookups", config.wallet.reload_nft_lookups),
+        ignore_off_chain_transactions: flag(
+            "ignore_off_chain_transactions",
+            config.wallet.ignore_off_chain_transactions,
+        ),
+        need_sk: false,
+    }
+}
 
-            let mut f = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .open(serialized_blocks_location)
-                .unwrap();
-            f.write_all(&all_blocks_serialized).unwrap();
-            f.flush().unwrap();
+fn run(command: &str, matches: &ArgMatches, output: &mut Output) -> Result<(), CliError> {
+    let config = match command {
+        // Take nothing from the config, so a broken config file can not stop
+        // them, least of all the subcommand that inspects it
+        "generate" | "random" | "bench" | "verify" | "key" | "piximg" | "config" => {
+            Config::default()
         }
-    } else if let Some(matches) = matches.subcommand_matches("count") {
-        let data_dir = matches.value_of("data").unwrap();
-
-        let load =
-            |filename: &str| read(format!("{}/{}", data_dir, filename)).map_err(|e| e.to_string());
-
-        println!("Loading binary wallet...");
-        let bin_wallet = &BinaryWallet {
-            blockchain_bin: load("blockchain").unwrap(),
-            pk_bin: load("pk").unwrap(),
-            sk_bin: load("sk").unwrap(),
-            on_chain_transactions_bin: load("on_chain_transactions").unwrap(),
-            unspent_outputs_bin: load("unspent_outputs").unwrap(),
-            nft_lookups_bin: load("nft_lookups").unwrap(),
-            off_chain_transactions_bin: load("off_chain_transactions").unwrap(),
-        };
-        println!("Binary wallet loaded!");
-        println!("blockchain: {}", bin_wallet.blockchain_bin.len());
-        println!("pk_bin: {}", bin_wallet.pk_bin.len());
-        println!("sk_bin: {}", bin_wallet.sk_bin.len());
-        println!(
-            "on_chain_transactions_bin: {}",
-            bin_wallet.on_chain_transactions_bin.len()
-        );
-        println!(
-            "unspent_outputs_bin: {}",
-            bin_wallet.unspent_outputs_bin.len()
-        );
-        println!("nft_lookups_bin: {}", bin_wallet.nft_lookups_bin.len());
-        println!(
-            "off_chain_transactions_bin: {}",
-            bin_wallet.off_chain_transactions_bin.len()
-        );
-        println!("Loading wallet...");
-        let wallet = Wallet::from_binary(
-            bin_wallet,
-            env::var("RELOAD_UNSPENT_OUTPUTS").is_ok(),
-            env::var("RELOAD_NFT_LOOKUPS").is_ok(),
-            env::var("IGNORE_OFF_CHAIN_TRANSACTIONS").is_ok(),
+        _ => Config::load_layered(matches.value_of("config"))?.0,
+    };
+    let path = |name: &str| matches.value_of(name).unwrap().to_string();
+    let threads = || positive_arg(matches, "threads", config.mining.threads, DEFAULT_N_THREADS);
+    let par_work = || {
+        positive_arg(
+            matches,
+            "par_work",
+            config.mining.par_work,
+            DEFAULT_PAR_WORK,
         )
-        .unwrap();
-        println!("Wallet loaded!");
-
-        let pb = ProgressBar::with_message(
-            ProgressBar::new(wallet.off_chain_transactions.len() as u64),
-            "Loading candidates from off chain transactions",
-        );
-        pb.set_style(ProgressStyle::default_bar().template(DEFAULT_PROGRESSBAR_TEMPLATE));
-        let mut candidates: HashMap<
-            (u16, u16),
-            HashMap<[u8; PIXEL_HASH_SIZE], [u8; PIXEL_HASH_SIZE]>,
-        > = HashMap::new();
-        let pk = wallet.get_pk().unwrap();
-        let mut total_value_spent = 0;
-        let mut total_things_bought = 0;
-        for (_, transaction) in &wallet.off_chain_transactions {
-            pb.inc(1);
-            if let Ok(base_message) = transaction.get_base_transaction_message() {
-                let mut back_hash: [u8; PIXEL_HASH_SIZE] = [0u8; PIXEL_HASH_SIZE];
-                back_hash.copy_from_slice(&base_message[..PIXEL_HASH_SIZE]);
-                let x: u16 = ((base_message[PIXEL_HASH_SIZE] as u16) << 8)
-                    + (base_message[PIXEL_HASH_SIZE + 1] as u16);
-                let y: u16 = ((base_message[PIXEL_HASH_SIZE + 2] as u16) << 8)
-                    + (base_message[PIXEL_HASH_SIZE + 3] as u16);
-                let color: u8 = base_message[PIXEL_HASH_SIZE + 4];
-                if x < 1000 && y < 1000 && color < NUM_COLORS {
-                    let a = candidates
-                        .entry((x as u16, y as u16))
-                        .or_insert_with(HashMap::new);
-                    a.insert(calc_pixel_hash(x, y, color, back_hash), back_hash);
-                }
-            } else {
-                let things_bought = transaction
-                    .get_inputs()
-                    .iter()
-                    .map(|i| {
-                        wallet
-                            .get_transaction(&i.block_hash, &i.transaction_hash)
-                            .unwrap()
-                            .get_output(&i.output_index)
-                    })
-                    .filter(|o| o.value.is_id_transfer() && o.pk == pk)
-                    .count();
-                if things_bought > 0 {
-                    total_things_bought += things_bought;
-                    for output in transaction.get_outputs() {
-                        if output.pk == pk {
-                            if let Ok(value) = output.value.get_value() {
-                                total_value_spent += value;
-                            }
+    };
+    match command {
+        "generate" => generate::run(
+            &generate::Options {
+                blocks: path("blocks"),
+                sk: path("sk"),
+                encrypt: encrypt_arg(matches)?,
+                data: matches.value_of("data").map(String::from),
+            },
+            output,
+        ),
+        "random" => random::run(
+            &random::Options {
+                images: path("images"),
+                output: path("output"),
+                count: value_t!(matches.value_of("count"), usize).map_err(|e| {
+                    CliError::Usage(format!("Could not convert count param: {}", e))
+                })?,
+                size: value_t!(matches.value_of("size"), usize)
+                    .map_err(|e| CliError::Usage(format!("Could not convert size param: {}", e)))?,
+            },
+            output,
+        ),
+        "bench" => bench::run(
+            &bench::Options {
+                threads: positive_args(matches, "threads", default_thread_sweep())?,
+                par_work: positive_args(
+                    matches,
+                    "par_work",
+                    vec![DEFAULT_PAR_WORK / 4, DEFAULT_PAR_WORK, DEFAULT_PAR_WORK * 4],
+                )?,
+                samples: positive_arg(matches, "samples", None, 1)? as usize,
+                output: matches.value_of("output").map(String::from),
+            },
+            output,
+        ),
+        "verify" => verify::run(
+            &verify::Options {
+                blocks: path("blocks"),
+                data: matches.value_of("data").map(String::from),
+            },
+            output,
+        ),
+        "mine" => mine::run(
+            &mine::Options {
+                blocks: path("blocks"),
+                sk: path("sk"),
+                resume: matches.is_present("resume"),
+                restart: matches.is_present("restart"),
+                rechain: matches.is_present("rechain"),
+                threads: threads()?,
+                par_work: par_work()?,
+                passphrase: passphrase_arg(matches, "passphrase")?,
+            },
+            output,
+        ),
+        "inspect" => inspect::run(
+            &inspect::Options {
+                source: match (matches.value_of("blocks"), matches.value_of("transactions")) {
+                    (Some(blocks), _) => inspect::Source::Blocks {
+                        path: blocks.to_string(),
+                        data: matches.value_of("data").map(String::from),
+                    },
+                    (None, Some(transactions)) => inspect::Source::Transactions {
+                        path: transactions.to_string(),
+                    },
+                    (None, None) => inspect::Source::OffChain {
+                        data: data_dir_arg(matches, &config)?,
+                    },
+                },
+                selector: matches
+                    .value_of("SELECTOR")
+                    .map(inspect::Selector::parse)
+                    .transpose()?,
+            },
+            output,
+        ),
+        "count" => count::run(
+            &count::Options {
+                data: data_dir_arg(matches, &config)?,
+                load: load_options(matches, &config),
+            },
+            output,
+        ),
+        "collect" => collect::run(
+            &collect::Options {
+                data: data_dir_arg(matches, &config)?,
+                load: load_options(matches, &config),
+                no_backup: matches.is_present("no_backup"),
+                keep_backups: positive_arg(
+                    matches,
+                    "keep_backups",
+                    config.backup.keep,
+                    DEFAULT_BACKUP_KEEP,
+                )? as usize,
+                passphrase: passphrase_arg(matches, "passphrase")?,
+                dry_run: matches.is_present("dry_run"),
+                selection: collect::Selection {
+                    kind: match matches.value_of("only") {
+                        Some("pixel") => Some(collect::TransactionKind::Pixel),
+                        Some(_) => Some(collect::TransactionKind::Transfer),
+                        None => None,
+                    },
+                    pks: matches
+                        .values_of("pk")
+                        .into_iter()
+                        .flatten()
+                        .map(parse_pk)
+                        .collect::<Result<_, _>>()?,
+                    skip_newest: value_t!(matches, "skip_newest", usize).map_err(|e| {
+                        CliError::Usage(format!("Could not convert skip_newest param: {}", e))
+                    })?,
+                    max_transactions: optional_positive_arg(matches, "max_transactions")?,
+                    max_bytes: optional_positive_arg(matches, "max_bytes")?,
+                    max_blocks: optional_positive_arg(matches, "max_blocks")?,
+                },
+                mine: matches.is_present("mine"),
+                threads: threads()?,
+                par_work: par_work()?,
+            },
+            output,
+        ),
+        "backup" => match matches.subcommand() {
+            ("list", Some(matches)) => backup::run(
+                &backup::Options::List {
+                    data: data_dir_arg(matches, &config)?,
+                },
+                output,
+            ),
+            ("restore", Some(matches)) => backup::run(
+                &backup::Options::Restore {
+                    data: data_dir_arg(matches, &config)?,
+                    id: matches.value_of("ID").unwrap().to_string(),
+                    keep: positive_arg(matches, "keep", config.backup.keep, DEFAULT_BACKUP_KEEP)?
+                        as usize,
+                },
+                output,
+            ),
+            ("prune", Some(matches)) => backup::run(
+                &backup::Options::Prune {
+                    data: data_dir_arg(matches, &config)?,
+                    keep: positive_arg(matches, "keep", config.backup.keep, DEFAULT_BACKUP_KEEP)?
+                        as usize,
+                },
+                output,
+            ),
+            _ => Ok(()),
+        },
+        "balance" => balance::run(
+            &balance::Options {
+                data: data_dir_arg(matches, &config)?,
+                pk: matches.value_of("pk").map(parse_pk).transpose()?,
+            },
+            output,
+        ),
+        "utxo" => match matches.subcommand() {
+            ("list", Some(matches)) => utxo::run(
+                &utxo::Options::List {
+                    data: data_dir_arg(matches, &config)?,
+                    pks: matches
+                        .values_of("pk")
+                        .into_iter()
+                        .flatten()
+                        .map(parse_pk)
+                        .collect::<Result<_, _>>()?,
+                    kind: match matches.value_of("kind") {
+                        Some("value") => Some(utxo::ValueKind::Value),
+                        Some(_) => Some(utxo::ValueKind::IdTransfer),
+                        None => None,
+                    },
+                },
+                output,
+            ),
+            _ => Ok(()),
+        },
+        "send" => send::run(
+            &send::Options {
+                data: data_dir_arg(matches, &config)?,
+                sk: matches.value_of("sk").map(String::from),
+                passphrase: passphrase_arg(matches, "passphrase")?,
+                to: parse_pk(matches.value_of("to").unwrap())?,
+                amount: parse_cel(matches.value_of("amount").unwrap())?,
+                output: matches.value_of("output").map(String::from),
+                threads: threads()?,
+                par_work: par_work()?,
+            },
+            output,
+        ),
+        "key" => match matches.subcommand() {
+            ("new", Some(matches)) => key::run(
+                &key::Options::New {
+                    path: matches.value_of("FILE").unwrap().to_string(),
+                    encrypt: encrypt_arg(matches)?,
+                },
+                output,
+            ),
+            ("show", Some(matches)) => key::run(
+                &key::Options::Show {
+                    path: matches.value_of("FILE").unwrap().to_string(),
+                    passphrase: passphrase_arg(matches, "passphrase")?,
+                },
+                output,
+            ),
+            ("import", Some(matches)) => key::run(
+                &key::Options::Import {
+                    input: matches.value_of("INPUT").unwrap().to_string(),
+                    path: matches.value_of("FILE").unwrap().to_string(),
+                    passphrase: passphrase_arg(matches, "passphrase")?,
+                    encrypt: encrypt_arg(matches)?,
+                },
+                output,
+            ),
+            ("export", Some(matches)) => key::run(
+                &key::Options::Export {
+                    path: matches.value_of("FILE").unwrap().to_string(),
+                    passphrase: passphrase_arg(matches, "passphrase")?,
+                    format: value_t!(matches, "key_format", KeyFormat).map_err(|e| {
+                        CliError::Usage(format!("Could not convert key_format param: {}", e))
+                    })?,
+                    output: matches.value_of("output").map(String::from),
+                },
+                output,
+            ),
+            ("rekey", Some(matches)) => key::run(
+                &key::Options::Rekey {
+                    path: matches.value_of("FILE").unwrap().to_string(),
+                    passphrase: passphrase_arg(matches, "passphrase")?,
+                    new_passphrase: if matches.is_present("decrypt") {
+                        None
+                    } else {
+                        Some(passphrase_arg(matches, "new_passphrase")?)
+                    },
+                },
+                output,
+            ),
+            _ => Ok(()),
+        },
+        "config" => match matches.subcommand() {
+            ("show", Some(matches)) => commands::config::show(
+                &commands::config::Options {
+                    path: matches.value_of("config").map(String::from),
+                },
+                output,
+            ),
+            ("path", Some(matches)) => commands::config::path(
+                &commands::config::Options {
+                    path: matches.value_of("config").map(String::from),
+                },
+                output,
+            ),
+            _ => Ok(()),
+        },
+        "piximg" => piximg::run(
+            &piximg::Options {
+                file: path("FILE"),
+                directory: path("DIRECTORY"),
+            },
+            output,
+        ),
+        "paint" => paint::run(
+            &paint::Options {
+                server_url: matches
+                    .value_of("server")
+                    .map(String::from)
+                    .or_else(|| config.server_url.clone())
+                    .unwrap_or_else(|| DEFAULT_SERVER_URL.to_string()),
+                pk: paint_pk(matches, &config)?,
+                pixels: match matches.value_of("image") {
+                    Some(image) => {
+                        let at = values_t!(matches, "at", u16).map_err(|e| {
+                            CliError::Usage(format!("Could not convert at param: {}", e))
+                        })?;
+                        paint::Pixels::Image {
+                            path: image.to_string(),
+                            at: (at[0], at[1]),
+                            dither: matches.is_present("dither"),
                         }
                     }
-                }
-            }
-        }
-        pb.finish();
-
-        let pb = ProgressBar::with_message(
-            ProgressBar::new(candidates.len() as u64),
-            "Processing candidates",
-        );
-        pb.set_style(ProgressStyle::default_bar().template(DEFAULT_PROGRESSBAR_TEMPLATE));
-        let mut total_set_pixels_unique = 0;
-        let mut total_set_pixels = 0;
-        for ((x, y), candidate) in candidates.iter() {
-            pb.inc(1);
-            let mut longest_candidate = (None, 0);
-            let mut to_digest = [0u8; 33];
-            to_digest[PIXEL_HASH_SIZE] = (x >> 8) as u8;
-            to_digest[PIXEL_HASH_SIZE + 1] = (x & 0xff) as u8;
-            to_digest[PIXEL_HASH_SIZE + 2] = (y >> 8) as u8;
-            to_digest[PIXEL_HASH_SIZE + 3] = (y & 0xff) as u8;
-            to_digest[PIXEL_HASH_SIZE + 4] = 7u8;
-            let mut init_hash = [0u8; PIXEL_HASH_SIZE];
-            init_hash.copy_from_slice(&Sha3_224::digest(&to_digest));
-            for (hash, back_hash) in candidate {
-                let mut len = 1;
-                let mut back_item = back_hash;
-                while let Some(tmp_back_item) = candidate.get(back_item) {
-                    back_item = tmp_back_item;
-                    len += 1;
-                }
-                if len > longest_candidate.1 && *back_item == init_hash {
-                    longest_candidate = (Some(hash), len);
-                }
-            }
-
-            if let (Some(_), len) = longest_candidate {
-                total_set_pixels_unique += 1;
-                total_set_pixels += len;
-            }
-        }
-        pb.finish();
-
-        println!("------------------------------------------------------");
-        println!("Total things bought from us: {}", total_things_bought);
-        println!(
-            "Total value spent on us: {}.{:0width$}",
-            total_value_spent / DUST_PER_CEL,
-            total_value_spent % DUST_PER_CEL,
-            width = 31
-        );
-
-        println!(
-            "Found {} unique pixels out of {} total set",
-            total_set_pixels_unique, total_set_pixels
-        );
-        println!(
-            "{} unique users have interacted with the blockchain",
-            wallet.unspent_outputs.len()
-        );
-    } else if let Some(matches) = matches.subcommand_matches("collect") {
-        let data_dir = matches.value_of("data").unwrap();
-
-        let load =
-            |filename: &str| read(format!("{}/{}", data_dir, filename)).map_err(|e| e.to_string());
-
-        println!("Loading binary wallet...");
-        let bin_wallet = &BinaryWallet {
-            blockchain_bin: load("blockchain").unwrap(),
-            pk_bin: load("pk").unwrap(),
-            sk_bin: load("sk").unwrap(),
-            on_chain_transactions_bin: load("on_chain_transactions").unwrap(),
-            unspent_outputs_bin: load("unspent_outputs").unwrap(),
-            nft_lookups_bin: load("nft_lookups").unwrap(),
-            off_chain_transactions_bin: load("off_chain_transactions").unwrap(),
-        };
-        println!("Binary wallet loaded!");
-        println!("blockchain: {}", bin_wallet.blockchain_bin.len());
-        println!("pk_bin: {}", bin_wallet.pk_bin.len());
-        println!("sk_bin: {}", bin_wallet.sk_bin.len());
-        println!(
-            "on_chain_transactions_bin: {}",
-            bin_wallet.on_chain_transactions_bin.len()
-        );
-        println!(
-            "unspent_outputs_bin: {}",
-            bin_wallet.unspent_outputs_bin.len()
-        );
-        println!("nft_lookups_bin: {}", bin_wallet.nft_lookups_bin.len());
-        println!(
-            "off_chain_transactions_bin: {}",
-            bin_wallet.off_chain_transactions_bin.len()
-        );
-        println!("Loading wallet...");
-        let mut wallet = Wallet::from_binary(
-            bin_wallet,
-            env::var("RELOAD_UNSPENT_OUTPUTS").is_ok(),
-            env::var("RELOAD_NFT_LOOKUPS").is_ok(),
-            env::var("IGNORE_OFF_CHAIN_TRANSACTIONS").is_ok(),
-        )
-        .unwrap();
-        println!(
-            "Wallet loaded! {} | {}",
-            wallet.count_blocks(),
-            wallet.on_chain_transactions.len()
-        );
-
-        let (block, transactions) = wallet.mining_data_from_off_chain_transactions().unwrap();
-
-        println!(
-            "Block {} created from {} transactions",
-            block.hash(),
-            transactions.len()
-        );
-
-        wallet
-            .add_on_chain_transactions(transactions, block.hash(), block.transactions_hash().hash())
-            .unwrap();
-
-        if wallet.off_chain_transactions.len() != 0 {
-            println!("ERROR: {} off chain transactions still in wallet after collecting all off chain transactions", wallet.off_chain_transactions.len());
-            return;
-        }
-
-        wallet.add_block(block).unwrap();
-        println!(
-            "Wallet loaded! {} | {}",
-            wallet.count_blocks(),
-            wallet.on_chain_transactions.len()
-        );
-        let binary_wallet = wallet.to_binary().unwrap();
-        let save = |filename: &str, data: Vec<u8>| {
-            File::create(format!("{}/{}", data_dir, filename))
-                .map(|mut f| f.write_all(&data).map_err(|e| e.to_string()))
-                .map_err(|e| e.to_string())
-        };
-        save("blockchain", binary_wallet.blockchain_bin)
-            .unwrap()
-            .unwrap();
-        save("pk", binary_wallet.pk_bin).unwrap().unwrap();
-        save("sk", binary_wallet.sk_bin).unwrap().unwrap();
-        save(
-            "on_chain_transactions",
-            binary_wallet.on_chain_transactions_bin,
-        )
-        .unwrap()
-        .unwrap();
-        save("unspent_outputs", binary_wallet.unspent_outputs_bin)
-            .unwrap()
-            .unwrap();
-        save("nft_lookups", binary_wallet.nft_lookups_bin)
-            .unwrap()
-            .unwrap();
-        save(
-            "off_chain_transactions",
-            binary_wallet.off_chain_transactions_bin,
-        )
-        .unwrap()
-        .unwrap();
-    } else if let Some(matches) = matches.subcommand_matches("piximg") {
-        let off_chain_transactions_file = matches.value_of("FILE").unwrap();
-        let output = matches.value_of("DIRECTORY").unwrap();
-        let load = |filename: &str| read(filename).map_err(|e| e.to_string());
-        let off_chain_transactions_bin = load(off_chain_transactions_file).unwrap();
-        let mut i = 0;
-        let mut ii = 0;
-        let mut image = RgbaImage::new(1000, 1000);
-        for y in 0..1000 {
-            for x in 0..1000 {
-                image.put_pixel(x, y, Rgba([0xff, 0xff, 0xff, 0xff]));
-            }
-        }
-        let mut pixel_base_messages = Vec::new();
-        while i < off_chain_transactions_bin.len() {
-            let t = *Transaction::from_serialized(&off_chain_transactions_bin, &mut i).unwrap();
-            if let Ok(bytes) = t.get_base_transaction_message() {
-                pixel_base_messages.push((bytes, format!("{}{:0>10}.png", output, ii)));
-                ii += 1;
-            }
-        }
-
-        let pb = ProgressBar::with_message(
-            ProgressBar::new(pixel_base_messages.len() as u64),
-            "Generating frames",
-        );
-        pb.set_style(ProgressStyle::default_bar().template(DEFAULT_PROGRESSBAR_TEMPLATE));
-        for (bytes, file_name) in pixel_base_messages {
-            pb.inc(1);
-            let x = ((bytes[28] as usize) << 8) + (bytes[29] as usize);
-            let y = ((bytes[30] as usize) << 8) + (bytes[31] as usize);
-            let color = COLOR_MAP[bytes[32] as usize];
-            if x < 1000 && y < 1000 {
-                image.put_pixel(x as u32, y as u32, Rgba(color));
-                image.save_with_format(file_name, ImageFormat::Png).unwrap();
-            }
-        }
-        pb.finish();
-        // ffmpeg -r 120 -i video/%10d.png -c:v libx265 canvas.mp4
-    } else if let Some(_) = matches.subcommand_matches("doit") {
-        let mut client = ClientBuilder::new("wss://api.celestium.space")
-            .unwrap()
-            .connect(None)
-            .unwrap();
-        let thread_pool = ThreadPoolBuilder::new()
-            .num_threads(wallet::DEFAULT_N_THREADS as usize)
-            .build()
-            .unwrap();
-        let pk_bin =
-            hex::decode("02cd09eaabf7cbee6f286265e31cafd88342d2446264f57b9033a29f289513b1e6")
-                .unwrap();
-        let pk = *PublicKey::from_serialized(&pk_bin, &mut 0).unwrap();
-
-        let mut file = File::open("pixels.json").unwrap();
-        let mut data = String::new();
-        file.read_to_string(&mut data).unwrap();
-
-        let mut json: Data = serde_json::from_str(&data).unwrap();
-        json.data.shuffle(&mut thread_rng());
-
-        let pb = ProgressBar::with_message(ProgressBar::new(json.data.len() as u64), "Mining...");
-        pb.set_style(ProgressStyle::default_bar().template(wallet::DEFAULT_PROGRESSBAR_TEMPLATE));
-        for [x, y, c] in json.data {
-            let xh = (x >> 8) as u8;
-            let xl = (x & 0xff) as u8;
-            let yh = (y >> 8) as u8;
-            let yl = (y & 0xff) as u8;
-
-            let bin_message = vec![0x00, xh, xl, yh, yl];
-            let message = Message::binary(bin_message);
-            client.send_message(&message).unwrap();
-            let mut get_pixel_color_response = vec![];
-            loop {
-                client
-                    .recv_message()
-                    .unwrap()
-                    .write_payload(&mut get_pixel_color_response)
-                    .unwrap();
-                if get_pixel_color_response[0] == 0x01 {
-                    break;
-                } else {
-                    if let Ok(txt_response) = std::str::from_utf8(&get_pixel_color_response) {
-                        println!("ERR: {}", txt_response);
-                    }
-                    get_pixel_color_response = vec![];
-                }
-            }
-            if get_pixel_color_response[1] == c as u8 {
-                println!("({}, {}) already set to {}, skipping", x, y, c);
-                continue;
-            }
-
-            let mut bin_message = vec![0x07, xh, xl, yh, yl];
-            bin_message.extend(pk_bin.clone());
-            let message = Message::binary(bin_message);
-            client.send_message(&message).unwrap();
-            let mut response = vec![];
-            loop {
-                client
-                    .recv_message()
-                    .unwrap()
-                    .write_payload(&mut response)
-                    .unwrap();
-                if response[0] == 0x08 {
-                    break;
-                } else {
-                    if let Ok(txt_response) = std::str::from_utf8(&response) {
-                        println!("ERR: {}", txt_response);
-                    }
-                    response = vec![];
-                }
-            }
-            let mut i = 1; // Skipping opcode, checked above
-            let mut message = [0x0; 33];
-            message[0..28].copy_from_slice(&response[i..i + 28]);
-            message[28] = xh;
-            message[29] = xl;
-            message[30] = yh;
-            message[31] = yl;
-            message[32] = c as u8;
-
-            i += 28;
-            let block_head_hash =
-                *BlockHash::from_serialized(&response[i..i + 32], &mut 0).unwrap();
-            i += 32;
-            let mut hash = [0u8; 32];
-            hash.copy_from_slice(&Sha3_256::digest(&message));
-            let pixel_transaction = Transaction::new_id_base_transaction(
-                block_head_hash,
-                message,
-                TransactionOutput::new(TransactionValue::new_id_transfer(hash).unwrap(), pk),
-            )
-            .unwrap();
-
-            let pixel_transaction = Wallet::mine_transaction(
-                wallet::DEFAULT_N_THREADS,
-                wallet::DEFAULT_PAR_WORK,
-                pixel_transaction,
-                &thread_pool,
-            )
-            .unwrap();
-
-            let katjing_transaction = *Transaction::from_serialized(&response, &mut i).unwrap();
-            let katjing_transaction = Wallet::mine_transaction(
-                wallet::DEFAULT_N_THREADS,
-                wallet::DEFAULT_PAR_WORK,
-                katjing_transaction,
-                &thread_pool,
-            )
-            .unwrap();
-
-            let mut bin_message = vec![
-                0u8;
-                1 + pixel_transaction.serialized_len()
-                    + katjing_transaction.serialized_len()
-            ];
-            bin_message[0] = 0x6;
-            let mut i = 1;
-            pixel_transaction
-                .serialize_into(&mut bin_message, &mut i)
-                .unwrap();
-            katjing_transaction
-                .serialize_into(&mut bin_message, &mut i)
-                .unwrap();
-            let message = Message::binary(bin_message);
-            client.send_message(&message).unwrap();
-
-            pb.inc(1);
-        }
-        pb.finish();
+                    None => paint::Pixels::File(
+                        matches
+                            .value_of("pixels")
+                            .map(String::from)
+                            .or_else(|| config.paint.pixels.clone())
+                            .unwrap_or_else(|| DEFAULT_PIXELS_PATH.to_string()),
+                    ),
+                },
+                threads: threads()?,
+                par_work: par_work()?,
+            },
+            output,
+        ),
+        _ => Ok(()),
     }
 }
@@ -0,0 +1,96 @@
+use crate::error::CliError;
+use celestium::wallet::DEFAULT_PROGRESSBAR_TEMPLATE;
+use colored::*;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::{json, Value};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    Text,
+    Json,
+    Ndjson,
+}
+
+/// Emits the records of a subcommand, either as coloured text for humans or
+/// as JSON objects sharing a `command`/`event` schema for machines.
+pub struct Output {
+    format: Format,
+    command: String,
+    records: Vec<Value>,
+}
+
+impl Output {
+    pub fn new(format: Format, command: &str) -> Output {
+        Output {
+            format,
+            command: command.to_string(),
+            records: Vec::new(),
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        self.format == Format::Text
+    }
+
+    /// Prints an informational line, only shown in text mode.
+    pub fn info<S: AsRef<str>>(&self, text: S) {
+        if self.is_text() {
+            println!("{}", text.as_ref());
+        }
+    }
+
+    /// Emits `record` as an `event`, printing `text` instead in text mode.
+    pub fn emit<S: AsRef<str>>(&mut self, event: &str, record: Value, text: S) {
+        if self.is_text() {
+            println!("{}", text.as_ref());
+        } else {
+            self.record(event, record);
+        }
+    }
+
+    /// Emits `record` as an `event`, without any text counterpart.
+    pub fn record(&mut self, event: &str, mut record: Value) {
+        if let Value::Object(fields) = &mut record {
+            fields.insert("command".to_string(), json!(self.command));
+            fields.insert("event".to_string(), json!(event));
+        }
+        match self.format {
+            Format::Text => {}
+            Format::Json => self.records.push(record),
+            Format::Ndjson => println!("{}", record),
+        }
+    }
+
+    /// Reports the error a subcommand failed with, on stderr in text mode.
+    pub fn error(&mut self, e: &CliError) {
+        if self.is_text() {
+            eprintln!("{} {}", "Error:".red(), e);
+        } else {
+            self.record(
+                "error",
+                json!({
+                    "category": e.category(),
+                    "message": e.to_string(),
+                    "exit_code": e.exit_code(),
+                }),
+            );
+        }
+    }
+
+    pub fn progress_bar(&self, len: u64, message: &'static str) -> ProgressBar {
+        let pb = if self.is_text() {
+            ProgressBar::new(len)
+        } else {
+            ProgressBar::hidden()
+        };
+        let pb = ProgressBar::with_message(pb, message);
+        pb.set_style(ProgressStyle::default_bar().template(DEFAULT_PROGRESSBAR_TEMPLATE));
+        pb
+    }
+
+    pub fn finish(self) {
+        if self.format == Format::Json {
+            println!("{}", Value::Array(self.records));
+        }
+    }
+}
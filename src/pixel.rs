@@ -0,0 +1,190 @@
+use image::{Rgba, RgbaImage};
+use sha3::{Digest, Sha3_224};
+
+pub const PIXEL_HASH_SIZE: usize = 28;
+pub const NUM_COLORS: u8 = 56;
+
+pub const COLOR_MAP: [[u8; 4]; 57] = [
+    [0x00, 0x00, 0x00, 0xff],
+    [0xe5, 0x00, 0x00, 0xff],
+    [0x02, 0xbe, 0x01, 0xff],
+    [0x00, 0x00, 0xea, 0xff],
+    [0xf8, 0xf2, 0x08, 0xff],
+    [0xfd, 0x5e, 0xf8, 0xff],
+    [0x00, 0xd3, 0xdd, 0xff],
+    [0xff, 0xff, 0xff, 0xff],
+    [0x74, 0x15, 0xcd, 0xff],
+    [0xf3, 0xc9, 0x9d, 0xff],
+    [0x99, 0x99, 0x99, 0xff],
+    [0xe5, 0x95, 0x00, 0xff],
+    [0x00, 0x83, 0xc7, 0xff],
+    [0x34, 0x71, 0x15, 0xff],
+    [0x43, 0x27, 0x0a, 0xff],
+    [0x86, 0x5a, 0x48, 0xff],
+    // Leet h4cker colors
+    [0xc5, 0x00, 0x00, 0xff],
+    [0xff, 0x40, 0x40, 0xff],
+    [0x00, 0x9e, 0x00, 0xff],
+    [0x42, 0xfe, 0x41, 0xff],
+    [0x00, 0x00, 0xca, 0xff],
+    [0x40, 0x40, 0xff, 0xff],
+    [0xc5, 0xb9, 0x00, 0xff],
+    [0xff, 0xff, 0x40, 0xff],
+    [0xdd, 0x3e, 0xd8, 0xff],
+    [0xff, 0x9e, 0xff, 0xff],
+    [0x00, 0xb3, 0xbd, 0xff],
+    [0x40, 0xff, 0xff, 0xff],
+    [0x54, 0x00, 0xad, 0xff],
+    [0xb4, 0x55, 0xff, 0xff],
+    [0xd3, 0xa9, 0x7d, 0xff],
+    [0xff, 0xff, 0xdd, 0xff],
+    [0x79, 0x79, 0x79, 0xff],
+    [0xd9, 0xd9, 0xd9, 0xff],
+    [0xc5, 0x75, 0x00, 0xff],
+    [0xff, 0xd5, 0x40, 0xff],
+    [0x00, 0x63, 0xa7, 0xff],
+    [0x40, 0xc3, 0xff, 0xff],
+    [0x14, 0x51, 0x00, 0xff],
+    [0x74, 0xb1, 0x55, 0xff],
+    [0x23, 0x07, 0x00, 0xff],
+    [0x83, 0x67, 0x4a, 0xff],
+    [0x66, 0x3a, 0x28, 0xff],
+    [0xc6, 0x9a, 0x88, 0xff],
+    [0x11, 0x11, 0x11, 0xff],
+    [0x22, 0x22, 0x22, 0xff],
+    [0x33, 0x33, 0x33, 0xff],
+    [0x44, 0x44, 0x44, 0xff],
+    [0x55, 0x55, 0x55, 0xff],
+    [0x66, 0x66, 0x66, 0xff],
+    [0x77, 0x77, 0x77, 0xff],
+    [0x88, 0x88, 0x88, 0xff],
+    [0xaa, 0xaa, 0xaa, 0xff],
+    [0xbb, 0xbb, 0xbb, 0xff],
+    [0xcc, 0xcc, 0xcc, 0xff],
+    [0xdd, 0xdd, 0xdd, 0xff],
+    [0xee, 0xee, 0xee, 0xff],
+];
+
+pub fn calc_pixel_hash(
+    x: u16,
+    y: u16,
+    color: u8,
+    back_hash: [u8; PIXEL_HASH_SIZE],
+) -> [u8; PIXEL_HASH_SIZE] {
+    let mut to_digest = [0u8; 33];
+    to_digest[..PIXEL_HASH_SIZE].copy_from_slice(&back_hash);
+    to_digest[PIXEL_HASH_SIZE] = (x >> 8) as u8;
+    to_digest[PIXEL_HASH_SIZE + 1] = (x & 0xff) as u8;
+    to_digest[PIXEL_HASH_SIZE + 2] = (y >> 8) as u8;
+    to_digest[PIXEL_HASH_SIZE + 3] = (y & 0xff) as u8;
+    to_digest[PIXEL_HASH_SIZE + 4] = color as u8;
+    let mut hash = [0u8; PIXEL_HASH_SIZE];
+    hash.copy_from_slice(&Sha3_224::digest(&to_digest));
+    hash
+}
+
+/// A pixel as set by the message of a base transaction.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Pixel {
+    pub x: u16,
+    pub y: u16,
+    pub color: u8,
+    /// Hash of the pixel this one was painted over
+    pub back_hash: [u8; PIXEL_HASH_SIZE],
+}
+
+impl Pixel {
+    /// Decodes a base transaction message, `None` if it does not set a pixel
+    /// on the 1000x1000 board.
+    pub fn from_base_message(message: &[u8]) -> Option<Pixel> {
+        if message.len() < PIXEL_HASH_SIZE + 5 {
+            return None;
+        }
+        let mut back_hash = [0u8; PIXEL_HASH_SIZE];
+        back_hash.copy_from_slice(&message[..PIXEL_HASH_SIZE]);
+        let x = ((message[PIXEL_HASH_SIZE] as u16) << 8) + (message[PIXEL_HASH_SIZE + 1] as u16);
+        let y =
+            ((message[PIXEL_HASH_SIZE + 2] as u16) << 8) + (message[PIXEL_HASH_SIZE + 3] as u16);
+        let color = message[PIXEL_HASH_SIZE + 4];
+        if x < 1000 && y < 1000 && color < NUM_COLORS {
+            Some(Pixel {
+                x,
+                y,
+                color,
+                back_hash,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+fn color_distance(rgb: [f32; 3], color: u8) -> f32 {
+    let [r, g, b, _] = COLOR_MAP[color as usize];
+    let (dr, dg, db) = (rgb[0] - r as f32, rgb[1] - g as f32, rgb[2] - b as f32);
+    dr * dr + dg * dg + db * db
+}
+
+/// Index of the color in `COLOR_MAP` closest to `rgb`, out of the first
+/// `NUM_COLORS` that a pixel can be set to.
+pub fn nearest_color(rgb: [f32; 3]) -> u8 {
+    (0..NUM_COLORS)
+        .min_by(|&a, &b| {
+            color_distance(rgb, a)
+                .partial_cmp(&color_distance(rgb, b))
+                .unwrap()
+        })
+        .unwrap()
+}
+
+/// Quantizes `image` to the palette, spreading the error of each pixel to its
+/// neighbours with Floyd-Steinberg dithering if `dither` is set. Pixels that
+/// are less than half opaque are left out. Returns `(x, y, color)` relative to
+/// the top left corner of the image.
+pub fn quantize(image: &RgbaImage, dither: bool) -> Vec<(u32, u32, u8)> {
+    let (width, height) = image.dimensions();
+    let mut errors = vec![[0f32; 3]; (width * height) as usize];
+    let mut pixels = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let Rgba([r, g, b, a]) = *image.get_pixel(x, y);
+            if a < 0x80 {
+                continue;
+            }
+            let error = errors[(y * width + x) as usize];
+            let wanted = [
+                r as f32 + error[0],
+                g as f32 + error[1],
+                b as f32 + error[2],
+            ];
+            let color = nearest_color(wanted);
+            pixels.push((x, y, color));
+            if !dither {
+                continue;
+            }
+            let [cr, cg, cb, _] = COLOR_MAP[color as usize];
+            let diff = [
+                wanted[0] - cr as f32,
+                wanted[1] - cg as f32,
+                wanted[2] - cb as f32,
+            ];
+            for &(dx, dy, weight) in [
+                (1, 0, 7.0 / 16.0),
+                (-1, 1, 3.0 / 16.0),
+                (0, 1, 5.0 / 16.0),
+                (1, 1, 1.0 / 16.0),
+            ]
+            .iter()
+            {
+                let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                if nx >= 0 && nx < width as i64 && ny < height as i64 {
+                    let neighbour = &mut errors[(ny as u32 * width + nx as u32) as usize];
+                    for (channel, diff) in neighbour.iter_mut().zip(diff.iter()) {
+                        *channel += diff * weight;
+                    }
+                }
+            }
+        }
+    }
+    pixels
+}
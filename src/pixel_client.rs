@@ -0,0 +1,382 @@
+use crate::{error::CliError, pixel::PIXEL_HASH_SIZE};
+use celestium::{
+    block_hash::BlockHash,
+    serialize::{DynamicSized, Serialize},
+    transaction::Transaction,
+};
+use secp256k1::PublicKey;
+use websocket::{
+    stream::sync::NetworkStream,
+    sync::{client::ClientBuilder, Client},
+    Message, OwnedMessage,
+};
+
+const GET_PIXEL: u8 = 0x00;
+const PIXEL: u8 = 0x01;
+const SUBMIT_TRANSACTIONS: u8 = 0x06;
+const GET_MINING_DATA: u8 = 0x07;
+const MINING_DATA: u8 = 0x08;
+
+const BLOCK_HASH_SIZE: usize = 32;
+
+/// What the server hands out for mining a pixel transaction.
+pub struct MiningData {
+    /// Hash of the pixel's current state, which a new pixel builds on
+    pub pixel_back_hash: [u8; PIXEL_HASH_SIZE],
+    pub block_head_hash: BlockHash,
+    /// Unmined transaction paying for the pixel
+    pub katjing_transaction: Transaction,
+}
+
+/// Binary frames sent to the server.
+pub enum Request<'a> {
+    /// Asks for the color of a pixel, answered by [`Response::Pixel`]
+    GetPixel { x: u16, y: u16 },
+    /// Asks for what is needed to mine a pixel for `pk`, answered by
+    /// [`Response::MiningData`]
+    GetMiningData { x: u16, y: u16, pk: &'a PublicKey },
+    /// Hands mined transactions to the server, not answered
+    SubmitTransactions(&'a [Transaction]),
+}
+
+/// Binary frames received from the server.
+pub enum Response {
+    Pixel { color: u8 },
+    MiningData(MiningData),
+}
+
+impl Request<'_> {
+    pub fn opcode(&self) -> u8 {
+        match self {
+            Request::GetPixel { .. } => GET_PIXEL,
+            Request::GetMiningData { .. } => GET_MINING_DATA,
+            Request::SubmitTransactions(_) => SUBMIT_TRANSACTIONS,
+        }
+    }
+
+    /// Writes the request as the payload of a binary frame: the opcode
+    /// followed by the big endian coordinates and the key, or by the
+    /// serialized transactions one after the other.
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let mut frame = vec![self.opcode()];
+        match self {
+            Request::GetPixel { x, y } => {
+                frame.extend_from_slice(&x.to_be_bytes());
+                frame.extend_from_slice(&y.to_be_bytes());
+            }
+            Request::GetMiningData { x, y, pk } => {
+                frame.extend_from_slice(&x.to_be_bytes());
+                frame.extend_from_slice(&y.to_be_bytes());
+                frame.extend_from_slice(&pk.serialize());
+            }
+            Request::SubmitTransactions(transactions) => {
+                let len = transactions
+                    .iter()
+                    .map(|t| t.serialized_len())
+                    .sum::<usize>();
+                frame.resize(1 + len, 0);
+                let mut i = 1;
+                for transaction in transactions.iter() {
+                    transaction.serialize_into(&mut frame, &mut i)?;
+                }
+            }
+        }
+        Ok(frame)
+    }
+}
+
+impl Response {
+    pub fn opcode(&self) -> u8 {
+        match self {
+            Response::Pixel { .. } => PIXEL,
+            Response::MiningData(_) => MINING_DATA,
+        }
+    }
+
+    /// Reads the payload of a binary frame, which must hold exactly one
+    /// response.
+    pub fn decode(frame: &[u8]) -> Result<Response, String> {
+        let (&opcode, body) = frame
+            .split_first()
+            .ok_or_else(|| "Empty frame".to_string())?;
+        match opcode {
+            PIXEL => match body {
+                [color] => Ok(Response::Pixel { color: *color }),
+                _ => Err(format!("Pixel response is {}B, expected 2B", frame.len())),
+            },
+            MINING_DATA => {
+                let min_len = PIXEL_HASH_SIZE + BLOCK_HASH_SIZE;
+                if body.len() < min_len {
+                    return Err(format!(
+                        "Mining data response is {}B, expected at least {}B",
+                        frame.len(),
+                        1 + min_len
+                    ));
+                }
+                let (pixel_hash, rest) = body.split_at(PIXEL_HASH_SIZE);
+                let (block_hash, transaction) = rest.split_at(BLOCK_HASH_SIZE);
+                let mut pixel_back_hash = [0u8; PIXEL_HASH_SIZE];
+                pixel_back_hash.copy_from_slice(pixel_hash);
+                let block_head_hash = *BlockHash::from_serialized(block_hash, &mut 0)?;
+                let mut offset = 0;
+                let katjing_transaction = *Transaction::from_serialized(transaction, &mut offset)?;
+                if offset != transaction.len() {
+                    return Err(format!(
+                        "{} trailing bytes after the mining data response",
+                        transaction.len() - offset
+                    ));
+                }
+                Ok(Response::MiningData(MiningData {
+                    pixel_back_hash,
+                    block_head_hash,
+                    katjing_transaction,
+                }))
+            }
+            _ => Err(format!("Unknown response opcode 0x{:02x}", opcode)),
+        }
+    }
+}
+
+/// Client for the websocket protocol the Celestium canvas is served over.
+pub struct PixelClient {
+    client: Client<Box<dyn NetworkStream + Send>>,
+    /// Text frames received while waiting for responses, the server reports
+    /// errors this way
+    pub server_errors: Vec<String>,
+}
+
+impl PixelClient {
+    pub fn connect(url: &str) -> Result<PixelClient, CliError> {
+        let client = ClientBuilder::new(url)
+            .map_err(|e| CliError::Usage(format!("Invalid server URL \"{}\". {}", url, e)))?
+            .connect(None)
+            .map_err(|e| CliError::Network(e.to_string()))?;
+        Ok(PixelClient {
+            client,
+            server_errors: Vec::new(),
+        })
+    }
+
+    fn send(&mut self, request: &Request) -> Result<(), CliError> {
+        let frame = request.encode().map_err(CliError::Protocol)?;
+        self.client
+            .send_message(&Message::binary(frame))
+            .map_err(|e| CliError::Network(e.to_string()))
+    }
+
+    /// Waits for a response with `opcode`, skipping other responses.
+    fn recv(&mut self, opcode: u8) -> Result<Response, CliError> {
+        loop {
+            match self
+                .client
+                .recv_message()
+                .map_err(|e| CliError::Network(e.to_string()))?
+            {
+                OwnedMessage::Binary(frame) => {
+                    let response = Response::decode(&frame).map_err(CliError::Protocol)?;
+                    if response.opcode() == opcode {
+                        return Ok(response);
+                    }
+                }
+                OwnedMessage::Text(text) => self.server_errors.push(text),
+                OwnedMessage::Close(_) => {
+                    return Err(CliError::Network(
+                        "The server closed the connection".to_string(),
+                    ))
+                }
+                OwnedMessage::Ping(_) | OwnedMessage::Pong(_) => {}
+            }
+        }
+    }
+
+    pub fn get_pixel_color(&mut self, x: u16, y: u16) -> Result<u8, CliError> {
+        self.send(&Request::GetPixel { x, y })?;
+        match self.recv(PIXEL)? {
+            Response::Pixel { color } => Ok(color),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn request_mining_data(
+        &mut self,
+        x: u16,
+        y: u16,
+        pk: &PublicKey,
+    ) -> Result<MiningData, CliError> {
+        self.send(&Request::GetMiningData { x, y, pk })?;
+        match self.recv(MINING_DATA)? {
+            Response::MiningData(mining_data) => Ok(mining_data),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn submit_transactions(&mut self, transactions: &[Transaction]) -> Result<(), CliError> {
+        self.send(&Request::SubmitTransactions(transactions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use celestium::{transaction_output::TransactionOutput, transaction_value::TransactionValue};
+    use secp256k1::{Secp256k1, SecretKey};
+
+    const BLOCK_HASH: [u8; BLOCK_HASH_SIZE] = [0x33; BLOCK_HASH_SIZE];
+
+    /// A pixel reply for color 42, as the server lays it out.
+    const PIXEL_FRAME_HEX: &str = "012a";
+
+    /// Start of a mining data reply as the server lays it out: opcode, a
+    /// pixel back hash of bytes 0x00..0x1b and a block hash of bytes
+    /// 0xa0..0xbf. The katjing transaction follows.
+    const MINING_DATA_HEADER_HEX: &str = concat!(
+        "08",
+        "000102030405060708090a0b0c0d0e0f101112131415161718191a1b",
+        "a0a1a2a3a4a5a6a7a8a9aaabacadaeafb0b1b2b3b4b5b6b7b8b9babbbcbdbebf",
+    );
+
+    fn pk() -> PublicKey {
+        let sk = SecretKey::from_slice(&[7; 32]).unwrap();
+        PublicKey::from_secret_key(&Secp256k1::new(), &sk)
+    }
+
+    /// An unmined transaction like the katjing transaction the server hands
+    /// out with the mining data.
+    fn transaction(id: u8) -> Transaction {
+        let block_hash = *BlockHash::from_serialized(&BLOCK_HASH, &mut 0).unwrap();
+        Transaction::new_id_base_transaction(
+            block_hash,
+            [id; 33],
+            TransactionOutput::new(TransactionValue::new_id_transfer([id; 32]).unwrap(), pk()),
+        )
+        .unwrap()
+    }
+
+    fn serialized(transaction: &Transaction) -> Vec<u8> {
+        let mut data = vec![0u8; transaction.serialized_len()];
+        transaction.serialize_into(&mut data, &mut 0).unwrap();
+        data
+    }
+
+    fn mining_data_frame() -> Vec<u8> {
+        let mut frame = hex::decode(MINING_DATA_HEADER_HEX).unwrap();
+        frame.extend_from_slice(&serialized(&transaction(0x11)));
+        frame
+    }
+
+    fn decode_error(frame: &[u8]) -> String {
+        match Response::decode(frame) {
+            Err(e) => e,
+            Ok(response) => panic!("Decoded a response with opcode {}", response.opcode()),
+        }
+    }
+
+    #[test]
+    fn encodes_get_pixel() {
+        let frame = Request::GetPixel { x: 258, y: 999 }.encode().unwrap();
+        assert_eq!(frame, vec![0x00, 0x01, 0x02, 0x03, 0xe7]);
+    }
+
+    #[test]
+    fn encodes_get_mining_data() {
+        let pk = pk();
+        let frame = Request::GetMiningData {
+            x: 1,
+            y: 2,
+            pk: &pk,
+        }
+        .encode()
+        .unwrap();
+        assert_eq!(frame[..5], [0x07, 0x00, 0x01, 0x00, 0x02]);
+        assert_eq!(frame[5..], pk.serialize()[..]);
+    }
+
+    #[test]
+    fn encodes_submitted_transactions_back_to_back() {
+        let transactions = [transaction(0x11), transaction(0x22)];
+        let frame = Request::SubmitTransactions(&transactions).encode().unwrap();
+        assert_eq!(frame[0], SUBMIT_TRANSACTIONS);
+        let mut i = 1;
+        for transaction in &transactions {
+            let decoded = Transaction::from_serialized(&frame, &mut i).unwrap();
+            assert_eq!(serialized(&decoded), serialized(transaction));
+        }
+        assert_eq!(i, frame.len());
+    }
+
+    #[test]
+    fn decodes_pixel() {
+        match Response::decode(&hex::decode(PIXEL_FRAME_HEX).unwrap()) {
+            Ok(Response::Pixel { color }) => assert_eq!(color, 42),
+            Ok(response) => panic!("Decoded opcode {}", response.opcode()),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn decodes_mining_data() {
+        let mining_data = match Response::decode(&mining_data_frame()) {
+            Ok(Response::MiningData(mining_data)) => mining_data,
+            Ok(response) => panic!("Decoded opcode {}", response.opcode()),
+            Err(e) => panic!("{}", e),
+        };
+        assert_eq!(
+            hex::encode(mining_data.pixel_back_hash),
+            &MINING_DATA_HEADER_HEX[2..2 + 2 * PIXEL_HASH_SIZE]
+        );
+        assert_eq!(
+            hex::encode(mining_data.block_head_hash.hash()),
+            &MINING_DATA_HEADER_HEX[2 + 2 * PIXEL_HASH_SIZE..]
+        );
+        assert_eq!(
+            mining_data
+                .katjing_transaction
+                .get_base_transaction_message()
+                .unwrap()[..],
+            [0x11; 33][..]
+        );
+        assert_eq!(
+            serialized(&mining_data.katjing_transaction),
+            serialized(&transaction(0x11))
+        );
+    }
+
+    #[test]
+    fn rejects_empty_frame() {
+        assert_eq!(decode_error(&[]), "Empty frame");
+    }
+
+    #[test]
+    fn rejects_unknown_opcodes() {
+        for opcode in [0x00, 0x02, 0x06, 0x07, 0x09, 0xff].iter() {
+            assert_eq!(
+                decode_error(&[*opcode, 0x00]),
+                format!("Unknown response opcode 0x{:02x}", opcode)
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_pixel_of_wrong_length() {
+        decode_error(&[PIXEL]);
+        decode_error(&[PIXEL, 0x01, 0x02]);
+    }
+
+    #[test]
+    fn rejects_short_mining_data() {
+        let frame = mining_data_frame();
+        decode_error(&frame[..PIXEL_HASH_SIZE + BLOCK_HASH_SIZE]);
+        decode_error(&frame[..1 + PIXEL_HASH_SIZE + BLOCK_HASH_SIZE]);
+        decode_error(&frame[..frame.len() - 1]);
+    }
+
+    #[test]
+    fn rejects_trailing_bytes_after_mining_data() {
+        let mut frame = mining_data_frame();
+        frame.push(0x00);
+        assert_eq!(
+            decode_error(&frame),
+            "1 trailing bytes after the mining data response"
+        );
+    }
+}